@@ -9,6 +9,7 @@ mod nested {
 
 fn main() {
     funky_logger::init();
+    let _guard = funky_logger::flush_guard();
 
     if !log_enabled!(log::Level::Trace) {
         eprintln!("To see the full demo, try setting `RUST_LOG=log=trace`.");
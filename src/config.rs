@@ -0,0 +1,565 @@
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use error::Error;
+use event::LevelMapping;
+#[cfg(feature = "http")]
+use http::HttpLogsConfig;
+use limit::RateLimit;
+use tags;
+use tcp::TcpLogsConfig;
+use transport::Endpoint;
+use worker::Overflow;
+
+/// Settings for the Datadog side of the logger.
+///
+/// The defaults match what `formatted_builder` has always done: events go
+/// over UDP to a DogStatsD agent on `127.0.0.1:8125` from a socket bound to
+/// `127.0.0.1:0`. Set `socket_path` to use a Unix domain socket instead.
+///
+/// Settings left unset are read from the standard Datadog environment
+/// variables when the logger is built:
+///
+/// - `DD_DOGSTATSD_URL` (`udp://host:port` or `unix:///path/to/socket`), or
+///   `DD_AGENT_HOST` and `DD_DOGSTATSD_PORT`, for the agent address
+/// - `DD_ENV`, `DD_SERVICE` and `DD_VERSION` for the `env:`, `service:` and
+///   `version:` tags
+/// - `DD_TAGS` for extra tags, separated by commas or spaces
+/// - `DD_HOSTNAME` for the host name
+///
+/// ```no_run
+/// use funky_logger::DatadogConfig;
+///
+/// let config = DatadogConfig::new()
+///     .host("datadog-agent.monitoring")
+///     .port(8125)
+///     .namespace("billing");
+/// funky_logger::formatted_builder_with(config).unwrap().init();
+/// ```
+#[derive(Clone, Debug)]
+pub struct DatadogConfig {
+    pub(crate) host: Option<String>,
+    pub(crate) port: Option<u16>,
+    pub(crate) bind_addr: Option<String>,
+    pub(crate) socket_path: Option<PathBuf>,
+    pub(crate) write_timeout: Option<Duration>,
+    pub(crate) namespace: String,
+    pub(crate) hostname: Option<String>,
+    pub(crate) env: Option<String>,
+    pub(crate) service: Option<String>,
+    pub(crate) version: Option<String>,
+    pub(crate) env_tags: Vec<String>,
+    pub(crate) tags: Vec<(String, String)>,
+    pub(crate) tag_keys: Vec<String>,
+    pub(crate) levels: LevelMapping,
+    pub(crate) events: bool,
+    pub(crate) source_location: bool,
+    pub(crate) thread_tag: bool,
+    #[cfg(feature = "http")]
+    pub(crate) http_logs: Option<HttpLogsConfig>,
+    pub(crate) tcp_logs: Option<TcpLogsConfig>,
+    pub(crate) queue_capacity: usize,
+    pub(crate) overflow: Overflow,
+    pub(crate) flush_timeout: Duration,
+    pub(crate) block_timeout: Option<Duration>,
+    pub(crate) rate_limit: Option<RateLimit>,
+    pub(crate) sample_rate: f64,
+}
+
+impl Default for DatadogConfig {
+    fn default() -> Self {
+        DatadogConfig {
+            host: None,
+            port: None,
+            bind_addr: None,
+            socket_path: None,
+            write_timeout: Some(Duration::from_millis(100)),
+            namespace: String::new(),
+            hostname: None,
+            env: None,
+            service: None,
+            version: None,
+            env_tags: Vec::new(),
+            tags: Vec::new(),
+            tag_keys: Vec::new(),
+            levels: LevelMapping::default(),
+            events: true,
+            source_location: false,
+            thread_tag: false,
+            #[cfg(feature = "http")]
+            http_logs: None,
+            tcp_logs: None,
+            queue_capacity: 1024,
+            overflow: Overflow::default(),
+            flush_timeout: Duration::from_secs(5),
+            block_timeout: None,
+            rate_limit: None,
+            sample_rate: 1.0,
+        }
+    }
+}
+
+impl DatadogConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Host name or IP address of the agent. Defaults to `DD_AGENT_HOST`,
+    /// or `127.0.0.1`.
+    pub fn host<S: Into<String>>(mut self, host: S) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    /// DogStatsD port of the agent. Defaults to `DD_DOGSTATSD_PORT`, or 8125.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// Local address to send from, e.g. `10.0.0.5:0`.
+    ///
+    /// Defaults to an ephemeral port on `127.0.0.1` when the agent is on
+    /// the loopback interface, and on the unspecified address otherwise.
+    pub fn bind_addr<S: Into<String>>(mut self, addr: S) -> Self {
+        self.bind_addr = Some(addr.into());
+        self
+    }
+
+    /// Sends to the agent's Unix domain socket, e.g.
+    /// `/var/run/datadog/dsd.socket`, instead of over UDP.
+    ///
+    /// Takes precedence over `host` and `port`. Defaults to the path in a
+    /// `unix://` `DD_DOGSTATSD_URL`, unless `host` or `port` are set.
+    pub fn socket_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.socket_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// How long sending a single event may block, or `None` to wait forever.
+    /// Defaults to 100 milliseconds.
+    ///
+    /// Mostly matters for Unix sockets, where a busy agent makes senders
+    /// block instead of dropping datagrams.
+    pub fn write_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.write_timeout = timeout;
+        self
+    }
+
+    /// Prefixed to the title of every event. Empty by default.
+    ///
+    /// DogStatsD only namespaces metrics itself, so without this events
+    /// from different services are told apart by their tags alone.
+    pub fn namespace<S: Into<String>>(mut self, namespace: S) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    /// The host name sent with events and logs. Defaults to `DD_HOSTNAME`;
+    /// when unset the agent or intake fills in its own.
+    pub fn hostname<S: Into<String>>(mut self, hostname: S) -> Self {
+        self.hostname = Some(hostname.into());
+        self
+    }
+
+    /// The `env:` tag on every event. Defaults to `DD_ENV`.
+    pub fn env<S: Into<String>>(mut self, env: S) -> Self {
+        self.env = Some(env.into());
+        self
+    }
+
+    /// The `service:` tag on every event. Defaults to `DD_SERVICE`.
+    pub fn service<S: Into<String>>(mut self, service: S) -> Self {
+        self.service = Some(service.into());
+        self
+    }
+
+    /// The `version:` tag on every event. Defaults to `DD_VERSION`.
+    pub fn version<S: Into<String>>(mut self, version: S) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Adds a tag to every event, e.g. `.tag("region", "eu-west-1")`.
+    ///
+    /// Tags are normalized to Datadog's rules: lowercased, starting with a
+    /// letter, other characters than letters, digits and `_-:./` replaced
+    /// by underscores, and at most 200 characters long. Building the logger
+    /// fails if a key normalizes to nothing. `DD_TAGS` entries with the same
+    /// key are left out.
+    pub fn tag<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.tags.push((key.into(), value.into()));
+        self
+    }
+
+    /// Adds several tags at once, see `tag`.
+    pub fn tags<I, K, V>(mut self, tags: I) -> Self
+        where I: IntoIterator<Item = (K, V)>,
+              K: Into<String>,
+              V: Into<String>,
+    {
+        self.tags.extend(tags.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Sends the record key-value `key` as a tag rather than as an attribute
+    /// of logs or as text of events, e.g. `.tag_key("tenant")` turns
+    /// `info!(tenant = "acme"; ...)` into `tenant:acme`.
+    ///
+    /// Keep this to keys with few distinct values; every combination of tags
+    /// is indexed separately.
+    pub fn tag_key<K: Into<String>>(mut self, key: K) -> Self {
+        self.tag_keys.push(key.into());
+        self
+    }
+
+    /// Which alert type and priority events get for each log level.
+    pub fn level_mapping(mut self, levels: LevelMapping) -> Self {
+        self.levels = levels;
+        self
+    }
+
+    /// Whether records are sent as DogStatsD events. On by default.
+    ///
+    /// Turn this off when shipping logs through `http_logs` or `tcp_logs`
+    /// only.
+    pub fn events(mut self, enabled: bool) -> Self {
+        self.events = enabled;
+        self
+    }
+
+    /// Whether the file and line a record was logged from are sent, as
+    /// `logger.file` and `logger.line` attributes of logs and `file:` and
+    /// `line:` tags of events. Off by default, since a tag per line makes
+    /// for a lot of distinct tags.
+    pub fn source_location(mut self, enabled: bool) -> Self {
+        self.source_location = enabled;
+        self
+    }
+
+    /// Whether events are tagged with `thread:` and the name (or id) of the
+    /// thread that logged. Off by default. Logs always carry it as the
+    /// `logger.thread_name` attribute.
+    pub fn thread_tag(mut self, enabled: bool) -> Self {
+        self.thread_tag = enabled;
+        self
+    }
+
+    /// Also sends records to the Datadog Logs HTTP intake.
+    #[cfg(feature = "http")]
+    pub fn http_logs(mut self, logs: HttpLogsConfig) -> Self {
+        self.http_logs = Some(logs);
+        self
+    }
+
+    /// Also forwards records to a TCP port the agent tails for logs.
+    pub fn tcp_logs(mut self, logs: TcpLogsConfig) -> Self {
+        self.tcp_logs = Some(logs);
+        self
+    }
+
+    /// How many records may wait for the sender thread. Defaults to 1024.
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = capacity;
+        self
+    }
+
+    /// What to do when the queue is full. Defaults to `Overflow::DropNewest`.
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// How long `flush()` and `FlushGuard` wait for the queue to drain.
+    /// Defaults to 5 seconds.
+    pub fn flush_timeout(mut self, timeout: Duration) -> Self {
+        self.flush_timeout = timeout;
+        self
+    }
+
+    /// How long `Overflow::Block` waits for room before dropping the record.
+    /// Waits forever by default.
+    pub fn block_timeout(mut self, timeout: Duration) -> Self {
+        self.block_timeout = Some(timeout);
+        self
+    }
+
+    /// Limits how many records each callsite or module sends, with a
+    /// summary of what was suppressed. Unlimited by default. The console
+    /// isn't affected.
+    pub fn rate_limit(mut self, limit: RateLimit) -> Self {
+        self.rate_limit = Some(limit);
+        self
+    }
+
+    /// The fraction of records sent, picked at random, from 0.0 to 1.0.
+    /// Defaults to 1.0. The console isn't affected.
+    pub fn sample_rate(mut self, rate: f64) -> Self {
+        self.sample_rate = rate;
+        self
+    }
+
+    /// Fills in settings that weren't set explicitly from the environment,
+    /// looking variables up through `var`.
+    pub(crate) fn with_env<F>(mut self, var: F) -> Result<Self, Error>
+        where F: Fn(&str) -> Option<String>
+    {
+        let var = |name: &str| var(name).filter(|v| !v.trim().is_empty());
+
+        if let Some(url) = var("DD_DOGSTATSD_URL") {
+            match parse_dogstatsd_url(&url)? {
+                DogstatsdUrl::Udp(host, port) => {
+                    self.host = self.host.or(Some(host));
+                    self.port = self.port.or(Some(port));
+                }
+                DogstatsdUrl::Unix(path) => {
+                    if self.host.is_none() && self.port.is_none() {
+                        self.socket_path = self.socket_path.or(Some(path));
+                    }
+                }
+            }
+        }
+        if let Some(host) = var("DD_AGENT_HOST") {
+            self.host = self.host.or(Some(host));
+        }
+        if let Some(port) = var("DD_DOGSTATSD_PORT") {
+            let port = port.trim().parse().map_err(|_| {
+                Error::Config(format!("invalid DD_DOGSTATSD_PORT: {:?}", port))
+            })?;
+            self.port = self.port.or(Some(port));
+        }
+
+        self.hostname = self.hostname.or_else(|| var("DD_HOSTNAME"));
+        self.env = self.env.or_else(|| var("DD_ENV"));
+        self.service = self.service.or_else(|| var("DD_SERVICE"));
+        self.version = self.version.or_else(|| var("DD_VERSION"));
+
+        #[cfg(feature = "http")]
+        {
+            self.http_logs = self.http_logs.map(|logs| logs.with_env(var));
+        }
+
+        if let Some(tags) = var("DD_TAGS") {
+            // a bad entry set outside the program shouldn't cost the rest
+            self.env_tags = tags
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|tag| !tag.is_empty())
+                .filter_map(|tag| tags::parse(tag)
+                    .map_err(|e| eprintln!("funky_logger: {} in DD_TAGS, skipping it", e))
+                    .ok())
+                .collect();
+        }
+
+        Ok(self)
+    }
+
+    /// Normalized tags attached to every event.
+    pub(crate) fn static_tags(&self) -> Result<Vec<String>, Error> {
+        let unified = [("env", &self.env), ("service", &self.service), ("version", &self.version)];
+        let mut out = Vec::new();
+        for &(key, value) in &unified {
+            if let Some(ref value) = *value {
+                out.push(tags::key_value(key, value)?);
+            }
+        }
+        let explicit = self.tags.iter()
+            .map(|(key, value)| tags::key_value(key, value))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // DD_TAGS entries give way to a tag with the same key set any other way
+        let key = |tag: &str| tag.split(':').next().unwrap_or(tag).to_string();
+        let taken: Vec<String> = out.iter().chain(&explicit).map(|tag| key(tag)).collect();
+        out.extend(self.env_tags.iter()
+            .filter(|tag| !tag.contains(':') || !taken.contains(&key(tag)))
+            .cloned());
+        out.extend(explicit);
+        Ok(out)
+    }
+
+    fn host_or_default(&self) -> &str {
+        self.host.as_ref().map_or("127.0.0.1", String::as_str)
+    }
+
+    /// The `host:port` events are sent to.
+    pub(crate) fn agent_addr(&self) -> String {
+        let host = self.host_or_default();
+        let port = self.port.unwrap_or(8125);
+        if host.contains(':') && !host.starts_with('[') {
+            format!("[{}]:{}", host, port)
+        } else {
+            format!("{}:{}", host, port)
+        }
+    }
+
+    /// Where events are sent.
+    pub(crate) fn endpoint(&self) -> Endpoint {
+        match self.socket_path {
+            Some(ref path) => Endpoint::Unix(path.clone()),
+            None => Endpoint::Udp {
+                local: self.local_addr(),
+                agent: self.agent_addr(),
+            },
+        }
+    }
+
+    /// The local address the socket binds to.
+    pub(crate) fn local_addr(&self) -> String {
+        if let Some(ref addr) = self.bind_addr {
+            return addr.clone();
+        }
+
+        let host = self.host_or_default().trim_start_matches('[').trim_end_matches(']');
+        match host.parse::<IpAddr>() {
+            Ok(IpAddr::V6(ip)) if ip.is_loopback() => "[::1]:0",
+            Ok(IpAddr::V6(_)) => "[::]:0",
+            Ok(ip) if !ip.is_loopback() => "0.0.0.0:0",
+            Err(_) if host != "localhost" => "0.0.0.0:0",
+            _ => "127.0.0.1:0",
+        }.to_string()
+    }
+}
+
+enum DogstatsdUrl {
+    Udp(String, u16),
+    Unix(PathBuf),
+}
+
+/// Parses a `udp://host:port` or `unix:///path` URL as found in
+/// `DD_DOGSTATSD_URL`.
+fn parse_dogstatsd_url(url: &str) -> Result<DogstatsdUrl, Error> {
+    let invalid = || Error::Config(format!("invalid DD_DOGSTATSD_URL: {:?}", url));
+
+    let rest = if let Some(rest) = url.strip_prefix("udp://") {
+        rest
+    } else if let Some(path) = url.strip_prefix("unix://") {
+        if path.is_empty() {
+            return Err(invalid());
+        }
+        return Ok(DogstatsdUrl::Unix(PathBuf::from(path)));
+    } else {
+        return Err(invalid());
+    };
+
+    let rest = rest.trim_end_matches('/');
+    let (host, port) = match rest.rfind(':') {
+        // a bare IPv6 address without a port
+        Some(i) if rest.ends_with(']') || (rest[..i].contains(':') && !rest.starts_with('[')) => (rest, None),
+        Some(i) => (&rest[..i], Some(&rest[i + 1..])),
+        None => (rest, None),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return Err(invalid());
+    }
+
+    let port = match port {
+        Some(port) => port.parse().map_err(|_| invalid())?,
+        None => 8125,
+    };
+    Ok(DogstatsdUrl::Udp(host.to_string(), port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars.iter()
+            .map(|&(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn defaults_are_unchanged() {
+        let config = DatadogConfig::default();
+        assert_eq!(config.agent_addr(), "127.0.0.1:8125");
+        assert_eq!(config.local_addr(), "127.0.0.1:0");
+    }
+
+    #[test]
+    fn bind_address_follows_the_agent() {
+        let local = |host: &str| DatadogConfig::new().host(host).local_addr();
+        assert_eq!(local("localhost"), "127.0.0.1:0");
+        assert_eq!(local("10.1.2.3"), "0.0.0.0:0");
+        assert_eq!(local("datadog-agent"), "0.0.0.0:0");
+        assert_eq!(local("::1"), "[::1]:0");
+        assert_eq!(local("fd00::7"), "[::]:0");
+
+        let config = DatadogConfig::new().host("fd00::7").port(9125).bind_addr("[fd00::1]:0");
+        assert_eq!(config.agent_addr(), "[fd00::7]:9125");
+        assert_eq!(config.local_addr(), "[fd00::1]:0");
+    }
+
+    #[test]
+    fn reads_agent_address_from_env() {
+        let config = DatadogConfig::new()
+            .with_env(env(&[("DD_AGENT_HOST", "10.0.0.2"), ("DD_DOGSTATSD_PORT", "9125")]))
+            .unwrap();
+        assert_eq!(config.agent_addr(), "10.0.0.2:9125");
+
+        let config = DatadogConfig::new()
+            .with_env(env(&[("DD_DOGSTATSD_URL", "udp://[fd00::2]:8126"), ("DD_AGENT_HOST", "ignored")]))
+            .unwrap();
+        assert_eq!(config.agent_addr(), "[fd00::2]:8126");
+
+        let config = DatadogConfig::new()
+            .with_env(env(&[("DD_DOGSTATSD_URL", "udp://agent")]))
+            .unwrap();
+        assert_eq!(config.agent_addr(), "agent:8125");
+
+        let config = DatadogConfig::new()
+            .with_env(env(&[("DD_DOGSTATSD_URL", "unix:///var/run/datadog/dsd.socket")]))
+            .unwrap();
+        assert_eq!(config.endpoint(), Endpoint::Unix("/var/run/datadog/dsd.socket".into()));
+
+        // an explicit UDP address wins over a socket from the environment
+        let config = DatadogConfig::new()
+            .port(9125)
+            .with_env(env(&[("DD_DOGSTATSD_URL", "unix:///var/run/datadog/dsd.socket")]))
+            .unwrap();
+        assert_eq!(config.endpoint(), Endpoint::Udp {
+            local: "127.0.0.1:0".into(),
+            agent: "127.0.0.1:9125".into(),
+        });
+
+        assert!(DatadogConfig::new().with_env(env(&[("DD_DOGSTATSD_PORT", "lots")])).is_err());
+        assert!(DatadogConfig::new().with_env(env(&[("DD_DOGSTATSD_URL", "tcp://agent:1")])).is_err());
+    }
+
+    #[test]
+    fn explicit_settings_win_over_env() {
+        let config = DatadogConfig::new()
+            .host("agent")
+            .service("billing")
+            .tag("Pod", "XYZ")
+            .with_env(env(&[
+                ("DD_AGENT_HOST", "10.0.0.2"),
+                ("DD_DOGSTATSD_PORT", "9125"),
+                ("DD_SERVICE", "from-env"),
+                ("DD_ENV", "prod"),
+                ("DD_TAGS", "team:payments, region:eu pod:abc"),
+            ]))
+            .unwrap();
+        assert_eq!(config.agent_addr(), "agent:9125");
+        assert_eq!(config.static_tags().unwrap(), vec![
+            "env:prod", "service:billing", "team:payments", "region:eu", "pod:xyz",
+        ]);
+
+        let config = DatadogConfig::new()
+            .env("staging")
+            .with_env(env(&[("DD_TAGS", "env:prod,team:payments")]))
+            .unwrap();
+        assert_eq!(config.static_tags().unwrap(), vec!["env:staging", "team:payments"]);
+    }
+
+    #[test]
+    fn skips_invalid_env_tags() {
+        let config = DatadogConfig::new()
+            .with_env(env(&[("DD_TAGS", "42,team:x,!!")]))
+            .unwrap();
+        assert_eq!(config.static_tags().unwrap(), vec!["team:x"]);
+    }
+}
@@ -0,0 +1,53 @@
+use std::io::{self, Write};
+use std::time::SystemTime;
+
+use env_logger::Target;
+use log::Record;
+
+use format::{Context, Format, Layout};
+use kv::Fields;
+use trace::TraceContext;
+
+/// Writes records to stderr or stdout in the chosen format.
+pub(crate) struct Console {
+    target: Target,
+    format: Format,
+    layout: Layout,
+    start: SystemTime,
+}
+
+impl Console {
+    pub fn new(target: Target, format: Format, layout: Layout) -> Console {
+        Console {
+            target,
+            format,
+            layout,
+            start: SystemTime::now(),
+        }
+    }
+
+    pub fn log(&self, record: &Record, trace: Option<TraceContext>, mdc: &Fields) {
+        let ctx = Context {
+            start: self.start,
+            now: SystemTime::now(),
+            layout: &self.layout,
+            trace,
+            mdc,
+        };
+        let mut line = String::new();
+        self.format.write(record, &ctx, &mut line);
+
+        // a logger has nowhere to report its own write errors
+        let _ = match self.target {
+            Target::Stderr => io::stderr().lock().write_all(line.as_bytes()),
+            Target::Stdout => io::stdout().lock().write_all(line.as_bytes()),
+        };
+    }
+
+    pub fn flush(&self) {
+        let _ = match self.target {
+            Target::Stderr => io::stderr().flush(),
+            Target::Stdout => io::stdout().flush(),
+        };
+    }
+}
@@ -0,0 +1,320 @@
+use std::env;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use log::{Level, Record};
+
+use config::DatadogConfig;
+use encoder::{self, Event};
+use error::Error;
+use event::LevelMapping;
+use format;
+use kv::Fields;
+use limit::{Limiter, Verdict};
+#[cfg(feature = "http")]
+use http::HttpSink;
+use record::{OwnedRecord, Sink};
+use stats;
+use tags;
+use tcp::TcpSink;
+use trace::TraceContext;
+use transport::{self, Transport};
+use worker::{self, Handler, Worker};
+
+pub(crate) struct DogLevel(pub Level);
+impl fmt::Display for DogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Level::Trace => "trace",
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warning",
+            Level::Error => "error",
+        }.fmt(f)
+    }
+}
+
+/// The three letter label used in event titles.
+fn label(level: Level) -> &'static str {
+    match level {
+        Level::Trace => "TRC",
+        Level::Debug => "DBG",
+        Level::Info => "LOG",
+        Level::Warn => "WRN",
+        Level::Error => "ERR",
+    }
+}
+
+/// Copies records and queues them for the sender thread, which hands them
+/// to every enabled Datadog output.
+pub(crate) struct DatadogSink {
+    worker: Arc<Worker<OwnedRecord>>,
+    limiter: Option<Arc<Limiter>>,
+}
+
+impl DatadogSink {
+    pub fn new(config: DatadogConfig) -> Result<DatadogSink, Error> {
+        let config = config.with_env(|name| env::var(name).ok())?;
+        let tags = config.static_tags()?;
+        let limiter = Limiter::new(config.rate_limit, config.sample_rate)?.map(Arc::new);
+
+        let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+        if config.events {
+            sinks.push(Box::new(EventSink::new(&config, tags.clone())?));
+        }
+        #[cfg(feature = "http")]
+        {
+            if let Some(ref logs) = config.http_logs {
+                sinks.push(Box::new(HttpSink::new(logs, &config, &tags)?));
+            }
+        }
+        if let Some(ref logs) = config.tcp_logs {
+            sinks.push(Box::new(TcpSink::new(logs, &config, &tags)));
+        }
+
+        let worker = Worker::spawn(
+            config.queue_capacity,
+            config.overflow,
+            config.flush_timeout,
+            Dispatch::new(sinks, limiter.clone()),
+        ).map_err(Error::Datadog)?;
+        let worker = Arc::new(worker.block_timeout(config.block_timeout));
+        worker::register(&worker);
+
+        Ok(DatadogSink { worker, limiter })
+    }
+
+    pub fn log(&self, record: &Record, trace: Option<TraceContext>, mdc: Fields) {
+        // records logged by an error hook would only fail again, and ones
+        // logged while sending would be sent forever
+        if stats::in_hook() || worker::on_worker() {
+            return;
+        }
+
+        if let Some(ref limiter) = self.limiter {
+            match limiter.allow(record, Instant::now()) {
+                Verdict::Allow => {}
+                Verdict::Suppress => return,
+                // the sender thread reports the window once it closes
+                Verdict::Opened => return self.worker.wake(),
+            }
+        }
+
+        self.worker.push(OwnedRecord::new(record, trace, mdc));
+    }
+
+    pub fn flush(&self) {
+        self.worker.flush();
+    }
+}
+
+/// Runs on the sender thread: hands records to the sinks and reports
+/// suppression windows as they close.
+pub(crate) struct Dispatch {
+    sinks: Vec<Box<dyn Sink>>,
+    limiter: Option<Arc<Limiter>>,
+}
+
+impl Dispatch {
+    pub fn new(sinks: Vec<Box<dyn Sink>>, limiter: Option<Arc<Limiter>>) -> Dispatch {
+        Dispatch { sinks, limiter }
+    }
+
+    /// Sends a warning for every suppression window that has closed.
+    fn report_suppressed(&mut self) {
+        let summaries = match self.limiter {
+            Some(ref limiter) => limiter.closed(Instant::now()),
+            None => return,
+        };
+        for summary in summaries {
+            self.handle(OwnedRecord::new(&Record::builder()
+                .args(format_args!("{}", summary))
+                .level(Level::Warn)
+                .target("funky_logger")
+                .module_path(Some("funky_logger"))
+                .build(), None, Fields::new()));
+        }
+    }
+}
+
+impl Handler<OwnedRecord> for Dispatch {
+    fn handle(&mut self, record: OwnedRecord) {
+        for sink in &mut self.sinks {
+            sink.send(&record);
+        }
+    }
+
+    fn idle(&mut self) -> Option<Duration> {
+        self.report_suppressed();
+        let now = Instant::now();
+        let report = self.limiter.as_ref()
+            .and_then(|limiter| limiter.next_report())
+            .map(|at| at.saturating_duration_since(now));
+        self.sinks.iter_mut().filter_map(|sink| sink.idle()).chain(report).min()
+    }
+
+    fn flush(&mut self) -> bool {
+        self.report_suppressed();
+        // every sink gets flushed even after one fails
+        let mut ok = true;
+        for sink in &mut self.sinks {
+            ok &= sink.flush();
+        }
+        ok
+    }
+}
+
+/// Sends every record as a DogStatsD event.
+///
+/// Events have no attributes, so key-values not promoted to tags are
+/// appended to the text as `key=value`.
+struct EventSink {
+    transport: Box<dyn Transport>,
+    namespace: String,
+    hostname: Option<String>,
+    levels: LevelMapping,
+    tags: Vec<String>,
+    tag_keys: Vec<String>,
+    source_location: bool,
+    thread_tag: bool,
+}
+
+impl EventSink {
+    fn new(config: &DatadogConfig, tags: Vec<String>) -> Result<EventSink, Error> {
+        let transport = transport::open(&config.endpoint(), config.write_timeout)
+            .map_err(Error::Datadog)?;
+
+        Ok(EventSink {
+            transport,
+            namespace: config.namespace.clone(),
+            hostname: config.hostname.clone(),
+            levels: config.levels,
+            tags,
+            tag_keys: config.tag_keys.clone(),
+            source_location: config.source_location,
+            thread_tag: config.thread_tag,
+        })
+    }
+}
+
+impl Sink for EventSink {
+    fn send(&mut self, record: &OwnedRecord) {
+        // the time is sent as date_happened, so the title doesn't repeat it
+        let l = label(record.level);
+
+        let mut tags = self.tags.clone();
+        tags.push(format!("level:{}", DogLevel(record.level)));
+        let mut title = if let Some(ref module_path) = record.module_path {
+            tags.push(tags::normalize(&format!("module:{}", module_path)));
+            format!("[{}] {}", l, module_path)
+        } else {
+            format!("[{}]", l)
+        };
+        if !self.namespace.is_empty() {
+            title = format!("{} {}", self.namespace, title);
+        }
+
+        if self.source_location {
+            if let Some(ref file) = record.file {
+                tags.push(tags::normalize(&format!("file:{}", file)));
+            }
+            if let Some(line) = record.line {
+                tags.push(format!("line:{}", line));
+            }
+        }
+
+        if let Some(trace) = record.trace {
+            tags.extend(trace.tags());
+        }
+        if self.thread_tag {
+            tags.push(tags::normalize(&format!("thread:{}", record.thread)));
+        }
+
+        // promoted key-values become tags, the rest follow the message
+        tags.extend(tags::promoted(&record.fields, &self.tag_keys));
+        let mut text = record.message.clone();
+        for (key, value) in &record.fields {
+            if !self.tag_keys.contains(key) {
+                format::pair(&mut text, key, &value.to_string());
+            }
+        }
+
+        let (alert_type, priority) = self.levels.get(record.level);
+        let event = Event {
+            title,
+            text,
+            date_happened: record.time.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs()),
+            hostname: self.hostname.clone(),
+            alert_type: Some(alert_type),
+            priority: Some(priority),
+            tags,
+            ..Event::default()
+        };
+
+        match self.transport.send(&encoder::encode(&event)) {
+            Ok(()) => stats::record_sent(1),
+            Err(e) => stats::record_send_error(&e, 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::Mutex;
+
+    struct Capture(Arc<Mutex<Vec<String>>>);
+
+    impl Transport for Capture {
+        fn send(&mut self, datagram: &[u8]) -> io::Result<()> {
+            self.0.lock().unwrap().push(String::from_utf8_lossy(datagram).into_owned());
+            Ok(())
+        }
+    }
+
+    /// Encodes `record` as an event with `config`, returning the datagram.
+    fn event(config: DatadogConfig, record: &OwnedRecord) -> String {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let mut sink = EventSink::new(&config, Vec::new()).unwrap();
+        sink.transport = Box::new(Capture(sent.clone()));
+        sink.send(record);
+        let datagram = sent.lock().unwrap().pop().unwrap();
+        datagram
+    }
+
+    fn record() -> OwnedRecord {
+        OwnedRecord {
+            file: Some("src/disk.rs".into()),
+            line: Some(7),
+            ..OwnedRecord::test(Level::Warn, "app::disk", "disk almost full")
+        }
+    }
+
+    #[test]
+    fn tags_source_location_only_when_enabled() {
+        let datagram = event(DatadogConfig::new(), &record());
+        assert!(datagram.ends_with("|#level:warning,module:app::disk"), "{}", datagram);
+
+        let datagram = event(DatadogConfig::new().source_location(true), &record());
+        assert!(datagram.ends_with("|#level:warning,module:app::disk,file:src/disk.rs,line:7"), "{}", datagram);
+    }
+
+    #[test]
+    fn tags_the_thread_only_when_enabled() {
+        let mut record = record();
+        record.thread = "wörker 1".into();
+        let datagram = event(DatadogConfig::new(), &record);
+        assert!(!datagram.contains("thread:"), "{}", datagram);
+
+        let datagram = event(DatadogConfig::new().thread_tag(true), &record);
+        assert!(datagram.ends_with(",module:app::disk,thread:wörker_1"), "{}", datagram);
+    }
+
+    #[test]
+    fn title_has_the_level_and_module() {
+        let datagram = event(DatadogConfig::new().namespace("billing"), &record());
+        assert!(datagram.starts_with("_e{23,16}:billing [WRN] app::disk|disk almost full|d:1700000000|"), "{}", datagram);
+    }
+}
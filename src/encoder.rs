@@ -0,0 +1,182 @@
+use event::{AlertType, Priority};
+
+/// Longest event title Datadog keeps, in characters.
+const MAX_TITLE_CHARS: usize = 100;
+/// Longest event text Datadog keeps, in characters.
+const MAX_TEXT_CHARS: usize = 4000;
+/// The agent's default receive buffer; longer datagrams are dropped.
+const MAX_DATAGRAM_BYTES: usize = 8192;
+
+/// A DogStatsD event ready to be encoded.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Event {
+    pub title: String,
+    pub text: String,
+    /// Unix time in seconds.
+    pub date_happened: Option<u64>,
+    pub hostname: Option<String>,
+    pub aggregation_key: Option<String>,
+    pub priority: Option<Priority>,
+    pub source_type_name: Option<String>,
+    pub alert_type: Option<AlertType>,
+    pub tags: Vec<String>,
+}
+
+/// Encodes `event` as a DogStatsD datagram:
+///
+/// `_e{title.length,text.length}:title|text|d:timestamp|h:hostname|k:aggregation_key|p:priority|s:source_type_name|t:alert_type|#tag1,tag2`
+///
+/// Newlines in the title and text are escaped as `\n`, the title and text are
+/// cut to Datadog's limits, and the text is shortened further if the whole
+/// datagram wouldn't fit in the agent's buffer.
+pub(crate) fn encode(event: &Event) -> Vec<u8> {
+    let title = escape(&event.title, MAX_TITLE_CHARS, usize::MAX);
+
+    let mut tail = String::new();
+    if let Some(date) = event.date_happened {
+        tail.push_str(&format!("|d:{}", date));
+    }
+    push_field(&mut tail, "h", event.hostname.as_ref());
+    push_field(&mut tail, "k", event.aggregation_key.as_ref());
+    if let Some(priority) = event.priority {
+        tail.push_str(&format!("|p:{}", priority));
+    }
+    push_field(&mut tail, "s", event.source_type_name.as_ref());
+    if let Some(alert_type) = event.alert_type {
+        tail.push_str(&format!("|t:{}", alert_type));
+    }
+    if !event.tags.is_empty() {
+        tail.push_str("|#");
+        tail.push_str(&event.tags.join(","));
+    }
+
+    // the header can't be written before the text length is known, so
+    // budget for the longest one the text could need
+    let header_max = format!("_e{{{},{}}}:", title.len(), MAX_DATAGRAM_BYTES).len();
+    let budget = MAX_DATAGRAM_BYTES.saturating_sub(header_max + title.len() + 1 + tail.len());
+    let text = escape(&event.text, MAX_TEXT_CHARS, budget);
+
+    let mut buf = format!("_e{{{},{}}}:{}|{}", title.len(), text.len(), title, text);
+    buf.push_str(&tail);
+    buf.into_bytes()
+}
+
+/// Escapes newlines, keeping at most `max_chars` characters of `s` and at
+/// most `max_bytes` bytes of output.
+fn escape(s: &str, max_chars: usize, max_bytes: usize) -> String {
+    let mut out = String::with_capacity(s.len().min(max_bytes));
+    for c in s.chars().take(max_chars) {
+        let escaped = match c {
+            '\n' => "\\n",
+            '\r' => "",
+            _ => {
+                if out.len() + c.len_utf8() > max_bytes {
+                    break;
+                }
+                out.push(c);
+                continue;
+            }
+        };
+        if out.len() + escaped.len() > max_bytes {
+            break;
+        }
+        out.push_str(escaped);
+    }
+    out
+}
+
+/// Appends `|name:value`, with characters that would end the field removed.
+fn push_field(buf: &mut String, name: &str, value: Option<&String>) {
+    if let Some(value) = value {
+        buf.push('|');
+        buf.push_str(name);
+        buf.push(':');
+        buf.extend(value.chars().filter(|&c| c != '|' && c != '\n' && c != '\r'));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(title: &str, text: &str) -> Event {
+        Event {
+            title: title.into(),
+            text: text.into(),
+            ..Event::default()
+        }
+    }
+
+    #[test]
+    fn encodes_title_and_text() {
+        assert_eq!(encode(&event("An exception occurred", "Cannot parse CSV file")),
+            &b"_e{21,21}:An exception occurred|Cannot parse CSV file"[..]);
+        assert_eq!(encode(&event("empty", "")), &b"_e{5,0}:empty|"[..]);
+    }
+
+    #[test]
+    fn encodes_every_field_in_order() {
+        let event = Event {
+            title: "deploy".into(),
+            text: "v1.2.3 is out".into(),
+            date_happened: Some(1_700_000_000),
+            hostname: Some("web-1".into()),
+            aggregation_key: Some("deploys".into()),
+            priority: Some(Priority::Low),
+            source_type_name: Some("rust".into()),
+            alert_type: Some(AlertType::Success),
+            tags: vec!["env:prod".into(), "service:web".into()],
+        };
+        assert_eq!(encode(&event), &b"_e{6,13}:deploy|v1.2.3 is out|d:1700000000|h:web-1\
+            |k:deploys|p:low|s:rust|t:success|#env:prod,service:web"[..]);
+    }
+
+    #[test]
+    fn escapes_newlines_and_counts_escaped_bytes() {
+        assert_eq!(encode(&event("two\nlines", "a\r\nb\nc")),
+            &b"_e{10,7}:two\\nlines|a\\nb\\nc"[..]);
+    }
+
+    #[test]
+    fn lengths_are_in_bytes() {
+        assert_eq!(encode(&event("h\u{e4}h", "\u{1f436}")),
+            "_e{4,4}:h\u{e4}h|\u{1f436}".as_bytes());
+    }
+
+    #[test]
+    fn strips_separators_from_optional_fields() {
+        let event = Event {
+            hostname: Some("evil|t:error".into()),
+            ..event("t", "x")
+        };
+        assert_eq!(encode(&event), &b"_e{1,1}:t|x|h:evilt:error"[..]);
+    }
+
+    #[test]
+    fn truncates_title_and_text_to_datadog_limits() {
+        let encoded = encode(&event(&"t".repeat(150), &"x".repeat(5000)));
+        let expected = format!("_e{{100,4000}}:{}|{}", "t".repeat(100), "x".repeat(4000));
+        assert_eq!(encoded, expected.as_bytes());
+
+        // characters, not bytes, count towards the limit
+        let encoded = encode(&event(&"\u{e4}".repeat(150), ""));
+        let expected = format!("_e{{200,0}}:{}|", "\u{e4}".repeat(100));
+        assert_eq!(encoded, expected.as_bytes());
+    }
+
+    #[test]
+    fn shrinks_text_to_fit_the_datagram() {
+        // 3000 four-byte characters are within the character limit but
+        // would make a 12kB datagram
+        let encoded = encode(&event("big", &"\u{1f436}".repeat(3000)));
+        assert!(encoded.len() <= MAX_DATAGRAM_BYTES);
+        let expected = format!("_e{{3,8176}}:big|{}", "\u{1f436}".repeat(2044));
+        assert_eq!(encoded, expected.as_bytes());
+
+        // an escape is never cut in half
+        let encoded = encode(&event("big", &"\n".repeat(4000)));
+        let text = &encoded[encoded.iter().position(|&b| b == b'|').unwrap() + 1..];
+        assert_eq!(text.len() % 2, 0);
+        assert!(text.chunks(2).all(|pair| pair == b"\\n"));
+    }
+}
@@ -0,0 +1,42 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+
+use log::SetLoggerError;
+
+/// Errors returned while setting up the logger.
+#[derive(Debug)]
+pub enum Error {
+    /// A global logger was already installed.
+    SetLogger(SetLoggerError),
+    /// The Datadog client could not be created.
+    Datadog(io::Error),
+    /// A setting, usually from the environment, is invalid.
+    Config(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::SetLogger(ref e) => write!(f, "failed to set logger: {}", e),
+            Error::Datadog(ref e) => write!(f, "failed to set up datadog client: {}", e),
+            Error::Config(ref msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            Error::SetLogger(ref e) => Some(e),
+            Error::Datadog(ref e) => Some(e),
+            Error::Config(_) => None,
+        }
+    }
+}
+
+impl From<SetLoggerError> for Error {
+    fn from(e: SetLoggerError) -> Self {
+        Error::SetLogger(e)
+    }
+}
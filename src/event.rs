@@ -0,0 +1,105 @@
+use std::fmt;
+
+use log::Level;
+
+/// The `alert_type` of a Datadog event, which decides how it is shown in the
+/// event stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlertType {
+    Error,
+    Warning,
+    Info,
+    Success,
+}
+
+impl fmt::Display for AlertType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AlertType::Error => "error",
+            AlertType::Warning => "warning",
+            AlertType::Info => "info",
+            AlertType::Success => "success",
+        }.fmt(f)
+    }
+}
+
+/// The priority of a Datadog event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    Normal,
+    Low,
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Priority::Normal => "normal",
+            Priority::Low => "low",
+        }.fmt(f)
+    }
+}
+
+/// Which alert type and priority events get for each log level.
+///
+/// By default `Error` and `Warn` map to the `error` and `warning` alert
+/// types, everything else to `info`, and `Debug` and `Trace` events get low
+/// priority.
+///
+/// ```
+/// # extern crate funky_logger;
+/// # extern crate log;
+/// use funky_logger::{AlertType, LevelMapping, Priority};
+/// use log::Level;
+///
+/// // treat warnings as errors, and keep info events out of the default view
+/// let mapping = LevelMapping::default()
+///     .set(Level::Warn, AlertType::Error, Priority::Normal)
+///     .set(Level::Info, AlertType::Info, Priority::Low);
+/// assert_eq!(mapping.get(Level::Warn), (AlertType::Error, Priority::Normal));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LevelMapping {
+    // indexed by `Level as usize - 1`, from Error to Trace
+    table: [(AlertType, Priority); 5],
+}
+
+impl Default for LevelMapping {
+    fn default() -> Self {
+        LevelMapping {
+            table: [
+                (AlertType::Error, Priority::Normal),
+                (AlertType::Warning, Priority::Normal),
+                (AlertType::Info, Priority::Normal),
+                (AlertType::Info, Priority::Low),
+                (AlertType::Info, Priority::Low),
+            ],
+        }
+    }
+}
+
+impl LevelMapping {
+    /// Overrides the alert type and priority for `level`.
+    pub fn set(mut self, level: Level, alert_type: AlertType, priority: Priority) -> Self {
+        self.table[level as usize - 1] = (alert_type, priority);
+        self
+    }
+
+    pub fn get(&self, level: Level) -> (AlertType, Priority) {
+        self.table[level as usize - 1]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_mapping() {
+        let mapping = LevelMapping::default();
+        assert_eq!(mapping.get(Level::Error), (AlertType::Error, Priority::Normal));
+        assert_eq!(mapping.get(Level::Warn), (AlertType::Warning, Priority::Normal));
+        assert_eq!(mapping.get(Level::Info), (AlertType::Info, Priority::Normal));
+        assert_eq!(mapping.get(Level::Debug), (AlertType::Info, Priority::Low));
+        assert_eq!(mapping.get(Level::Trace), (AlertType::Info, Priority::Low));
+    }
+}
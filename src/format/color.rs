@@ -0,0 +1,88 @@
+use std::io::{self, IsTerminal};
+
+use env_logger::Target;
+
+/// Whether the console output is colored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Color when writing to a terminal, unless the environment says
+    /// otherwise.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Decides whether to color output written to a terminal or not.
+    ///
+    /// `Auto` checks, in order: `RUST_LOG_STYLE` (`always` or `never`, like
+    /// `env_logger`), `NO_COLOR` (any non-empty value disables color),
+    /// `CLICOLOR_FORCE` (anything but `0` enables it), and finally
+    /// `is_terminal`.
+    pub(crate) fn resolve<F>(self, is_terminal: bool, var: F) -> bool
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        match self {
+            ColorChoice::Always => return true,
+            ColorChoice::Never => return false,
+            ColorChoice::Auto => {}
+        }
+
+        match var("RUST_LOG_STYLE").as_ref().map(|s| s.trim()) {
+            Some("always") => return true,
+            Some("never") => return false,
+            _ => {}
+        }
+        if var("NO_COLOR").is_some_and(|s| !s.is_empty()) {
+            return false;
+        }
+        if var("CLICOLOR_FORCE").is_some_and(|s| !s.is_empty() && s != "0") {
+            return true;
+        }
+
+        is_terminal
+    }
+}
+
+/// Whether `target` is attached to a terminal.
+pub(crate) fn is_terminal(target: &Target) -> bool {
+    match *target {
+        Target::Stderr => io::stderr().is_terminal(),
+        Target::Stdout => io::stdout().is_terminal(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn resolve_on(choice: ColorChoice, is_terminal: bool, vars: &[(&str, &str)]) -> bool {
+        let vars: HashMap<_, _> = vars.iter().cloned().collect();
+        choice.resolve(is_terminal, |name| vars.get(name).map(|v| v.to_string()))
+    }
+
+    fn resolve(choice: ColorChoice, vars: &[(&str, &str)]) -> bool {
+        resolve_on(choice, false, vars)
+    }
+
+    #[test]
+    fn environment_overrides_detection() {
+        assert!(!resolve(ColorChoice::Auto, &[]));
+        assert!(resolve_on(ColorChoice::Auto, true, &[]));
+        assert!(!resolve_on(ColorChoice::Auto, true, &[("NO_COLOR", "1")]));
+        assert!(!resolve_on(ColorChoice::Auto, true, &[("RUST_LOG_STYLE", "never")]));
+        assert!(resolve(ColorChoice::Auto, &[("CLICOLOR_FORCE", "1")]));
+        assert!(!resolve(ColorChoice::Auto, &[("CLICOLOR_FORCE", "0")]));
+        assert!(!resolve(ColorChoice::Auto, &[("NO_COLOR", "1"), ("CLICOLOR_FORCE", "1")]));
+        assert!(resolve(ColorChoice::Auto, &[("NO_COLOR", ""), ("CLICOLOR_FORCE", "1")]));
+        assert!(resolve(ColorChoice::Auto, &[("RUST_LOG_STYLE", "always"), ("NO_COLOR", "1")]));
+        assert!(!resolve(ColorChoice::Auto, &[("RUST_LOG_STYLE", "never"), ("CLICOLOR_FORCE", "1")]));
+        assert!(resolve(ColorChoice::Auto, &[("RUST_LOG_STYLE", "auto"), ("CLICOLOR_FORCE", "1")]));
+
+        assert!(resolve(ColorChoice::Always, &[("NO_COLOR", "1")]));
+        assert!(!resolve(ColorChoice::Never, &[("CLICOLOR_FORCE", "1")]));
+    }
+}
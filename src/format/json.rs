@@ -0,0 +1,103 @@
+use std::time::UNIX_EPOCH;
+
+use log::Record;
+
+use datadog::DogLevel;
+use json::Object;
+use kv;
+use super::{thread_name, Context, Timestamp};
+
+// attributes written by `write` that key-values must not overwrite
+const RESERVED: &[&str] = &["timestamp", "status", "message", "logger", "dd"];
+
+/// Writes `record` as a single JSON line.
+///
+/// Field names are Datadog's reserved attributes: `timestamp` (an RFC 3339
+/// string in the RFC 3339 modes, otherwise milliseconds since the epoch),
+/// `status`, `message`, and `logger.name`
+/// (the target), `logger.module_path`, `logger.file`, `logger.line` and
+/// `logger.thread_name`, and `dd.trace_id` and `dd.span_id` when there is
+/// a trace. Key-values follow as top-level fields, prefixed
+/// with `kv.` if they'd clash with one of those.
+pub(crate) fn write(record: &Record, ctx: &Context, out: &mut String) {
+    {
+        let mut obj = Object::new(out);
+        match ctx.layout.timestamp {
+            Timestamp::Utc | Timestamp::Local => {
+                obj.string("timestamp", &ctx.time());
+            }
+            Timestamp::Uptime | Timestamp::Unix => {
+                if let Ok(since_epoch) = ctx.now.duration_since(UNIX_EPOCH) {
+                    obj.number("timestamp", since_epoch.as_millis() as u64);
+                }
+            }
+        }
+        obj.string("status", &DogLevel(record.level()).to_string())
+            .string("message", &record.args().to_string());
+
+        {
+            let mut logger = obj.object("logger");
+            logger.string("name", record.target());
+            if let Some(module_path) = record.module_path() {
+                logger.string("module_path", module_path);
+            }
+            if let Some(file) = record.file() {
+                logger.string("file", file);
+            }
+            if let Some(line) = record.line() {
+                logger.number("line", u64::from(line));
+            }
+            logger.string("thread_name", &thread_name()).finish();
+        }
+        if let Some(trace) = ctx.trace {
+            trace.write_json(&mut obj);
+        }
+        for (key, value) in ctx.fields(record) {
+            value.write_json(&mut obj, &kv::json_key(&key, RESERVED));
+        }
+        obj.finish();
+    }
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use format::Layout;
+    use trace::TraceContext;
+    use std::thread;
+    use std::time::Duration;
+
+    use log::Level;
+
+    #[test]
+    fn writes_one_object_per_line() {
+        let out = thread::Builder::new().name("worker-1".into()).spawn(|| {
+            let ctx = Context {
+                start: UNIX_EPOCH,
+                now: UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+                layout: &Layout::default(),
+                trace: Some(TraceContext::new(1234, 5678)),
+                mdc: &Vec::new(),
+            };
+            let mut out = String::new();
+            write(&Record::builder()
+                .args(format_args!("two\n\"lines\""))
+                .level(Level::Warn)
+                .target("app::db")
+                .module_path(Some("app::db"))
+                .file(Some("src/db.rs"))
+                .line(Some(42))
+                .key_values(&[("retries", 3)])
+                .build(), &ctx, &mut out);
+            out
+        }).unwrap().join().unwrap();
+
+        assert_eq!(out, concat!(
+            r#"{"timestamp":1700000000123,"status":"warning","message":"two\n\"lines\"","#,
+            r#""logger":{"name":"app::db","module_path":"app::db","file":"src/db.rs","line":42,"thread_name":"worker-1"},"#,
+            r#""dd":{"trace_id":"1234","span_id":"5678"},"retries":3}"#,
+            "\n",
+        ));
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt::Write;
+
+use log::Record;
+
+use datadog::DogLevel;
+use super::{thread_name, Context};
+
+/// Writes `record` as a single `key=value` line.
+///
+/// Key-values come after the fixed fields. The message always goes last as
+/// `msg`, and newlines in it are escaped
+/// rather than continued, so every record is exactly one line.
+pub(crate) fn write(record: &Record, ctx: &Context, out: &mut String) {
+    pair(out, "time", &ctx.time());
+    pair(out, "level", &DogLevel(record.level()).to_string());
+    if ctx.layout.thread_name {
+        pair(out, "thread", &thread_name());
+    }
+    pair(out, "target", record.target());
+    if let Some(module_path) = record.module_path() {
+        pair(out, "module", module_path);
+    }
+    for (key, value) in ctx.fields(record) {
+        pair(out, &key, &value.to_string());
+    }
+    pair(out, "msg", &record.args().to_string());
+    out.push('\n');
+}
+
+/// Appends ` key=value`, quoting the value when needed.
+pub(crate) fn pair(out: &mut String, key: &str, value: &str) {
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push(' ');
+    }
+    key_to(out, key);
+    out.push('=');
+    value_to(out, value);
+}
+
+fn needs_quotes(c: char) -> bool {
+    c == ' ' || c == '=' || c == '"' || c == '\\' || c.is_control()
+}
+
+/// Keys are never quoted: characters a value would need quotes for become
+/// `_`, and an empty key is written as `_`.
+fn key_to(out: &mut String, key: &str) {
+    if key.is_empty() {
+        out.push('_');
+    }
+    out.extend(key.chars().map(|c| if needs_quotes(c) { '_' } else { c }));
+}
+
+/// Values are written bare unless they are empty or contain a space, `=`,
+/// `"` or a control character; quoted values escape `"`, `\` and control
+/// characters.
+fn value_to(out: &mut String, value: &str) {
+    let bare = !value.is_empty() && !value.chars().any(needs_quotes);
+    if bare {
+        out.push_str(value);
+        return;
+    }
+
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{{{:x}}}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use log::Level;
+
+    use format::Layout;
+    use kv::Value;
+
+    fn quoted(value: &str) -> String {
+        let mut out = String::new();
+        value_to(&mut out, value);
+        out
+    }
+
+    #[test]
+    fn quotes_only_when_needed() {
+        assert_eq!(quoted("plain"), "plain");
+        assert_eq!(quoted("app::db"), "app::db");
+        assert_eq!(quoted(""), r#""""#);
+        assert_eq!(quoted("two words"), r#""two words""#);
+        assert_eq!(quoted("a=b"), r#""a=b""#);
+        assert_eq!(quoted(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(quoted(r"C:\temp"), r#""C:\\temp""#);
+        assert_eq!(quoted("tab\there\u{7}"), r#""tab\there\u{7}""#);
+
+        let mut out = String::new();
+        pair(&mut out, "user id", "7");
+        pair(&mut out, "a=\"b\"\n", "8");
+        pair(&mut out, "", "9");
+        assert_eq!(out, "user_id=7 a__b__=8 _=9");
+    }
+
+    #[test]
+    fn multiline_messages_stay_on_one_line_after_fields() {
+        let ctx = Context {
+            start: UNIX_EPOCH,
+            now: UNIX_EPOCH + Duration::from_millis(61_005),
+            layout: &Layout::default(),
+            trace: None,
+            mdc: &vec![
+                ("request_id".into(), Value::Str("r1".into())),
+                ("note".into(), Value::Str("from mdc".into())),
+            ],
+        };
+        let mut out = String::new();
+        write(&Record::builder()
+            .args(format_args!("first\r\nsecond"))
+            .level(Level::Info)
+            .target("app")
+            .module_path(Some("app::net"))
+            .key_values(&[("peer", "10.0.0.1:80"), ("note", "a b")])
+            .build(), &ctx, &mut out);
+
+        assert_eq!(out, "time=0:01:01.005 level=info target=app module=app::net request_id=r1 note=\"a b\" peer=10.0.0.1:80 msg=\"first\\r\\nsecond\"\n");
+    }
+}
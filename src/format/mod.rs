@@ -0,0 +1,135 @@
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+use std::thread;
+use std::time::SystemTime;
+
+use log::Record;
+
+use kv::{self, Fields};
+use trace::TraceContext;
+
+mod color;
+mod json;
+mod logfmt;
+mod pretty;
+mod theme;
+mod time;
+
+pub(crate) use self::logfmt::pair;
+pub(crate) use self::color::is_terminal;
+pub use self::color::ColorChoice;
+pub use self::theme::{Color, LevelTheme, Style, Theme};
+pub use self::time::{Precision, Timestamp};
+
+/// How records are laid out on the console.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// The colored `[LOG 0:00:01.234 module] message` layout.
+    #[default]
+    Pretty,
+    /// One JSON object per line, using Datadog's reserved attribute names so
+    /// the agent can parse it without a pipeline.
+    Json,
+    /// One line of `key=value` pairs, with the message last as `msg`.
+    Logfmt,
+}
+
+impl Format {
+    /// Reads `FUNKY_LOG_FORMAT`, falling back to `Pretty` if it's unset or
+    /// unknown.
+    pub(crate) fn from_env() -> Format {
+        match env::var("FUNKY_LOG_FORMAT") {
+            Ok(ref s) if !s.trim().is_empty() => s.parse().unwrap_or_else(|e| {
+                eprintln!("funky_logger: {}, using pretty output", e);
+                Format::Pretty
+            }),
+            _ => Format::Pretty,
+        }
+    }
+
+    pub(crate) fn write(self, record: &Record, ctx: &Context, out: &mut String) {
+        match self {
+            Format::Pretty => pretty::write(record, ctx, out),
+            Format::Json => json::write(record, ctx, out),
+            Format::Logfmt => logfmt::write(record, ctx, out),
+        }
+    }
+}
+
+/// Error returned when parsing an unknown `Format`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseFormatError(String);
+
+impl fmt::Display for ParseFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown log format {:?}, expected \"pretty\", \"json\" or \"logfmt\"", self.0)
+    }
+}
+
+impl ::std::error::Error for ParseFormatError {}
+
+impl FromStr for Format {
+    type Err = ParseFormatError;
+
+    fn from_str(s: &str) -> Result<Format, ParseFormatError> {
+        match s.trim().to_lowercase().as_str() {
+            "pretty" => Ok(Format::Pretty),
+            "json" => Ok(Format::Json),
+            "logfmt" => Ok(Format::Logfmt),
+            _ => Err(ParseFormatError(s.to_string())),
+        }
+    }
+}
+
+/// Console settings shared by every format.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Layout {
+    pub timestamp: Timestamp,
+    pub precision: Precision,
+    /// Whether to paint with ANSI escapes, already resolved for the target.
+    pub color: bool,
+    pub theme: Theme,
+    /// Whether the pretty header shows `file:line`.
+    pub source_location: bool,
+    /// Whether the pretty and logfmt layouts show the thread name.
+    pub thread_name: bool,
+}
+
+/// What a format needs besides the record itself.
+pub(crate) struct Context<'a> {
+    /// When the logger was built.
+    pub start: SystemTime,
+    /// When the record was logged.
+    pub now: SystemTime,
+    pub layout: &'a Layout,
+    /// The trace the record was logged in.
+    pub trace: Option<TraceContext>,
+    /// Fields of the entered `Mdc`s.
+    pub mdc: &'a Fields,
+}
+
+impl<'a> Context<'a> {
+    /// The record's timestamp in the configured mode and precision.
+    pub fn time(&self) -> String {
+        time::format(self.layout.timestamp, self.layout.precision, self.start, self.now)
+    }
+
+    /// The `Mdc` fields followed by the record's key-values.
+    pub fn fields(&self, record: &Record) -> Fields {
+        kv::merge(self.mdc.clone(), record)
+    }
+}
+
+/// The current thread's name, or its numeric id for unnamed threads.
+pub(crate) fn thread_name() -> String {
+    let thread = thread::current();
+    match thread.name() {
+        Some(name) => name.to_string(),
+        None => {
+            // `ThreadId` only exposes its number through `Debug`
+            let id = format!("{:?}", thread.id());
+            id.trim_start_matches("ThreadId(").trim_end_matches(')').to_string()
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use std::fmt::Write;
+
+use log::Record;
+
+use super::{logfmt, thread_name, Context};
+
+/// Writes `record` in the bracketed funky layout, indenting continuation
+/// lines to line up with the message. Key-values follow the message as
+/// `key=value`.
+pub(crate) fn write(record: &Record, ctx: &Context, out: &mut String) {
+    let time = ctx.time();
+    let theme = &ctx.layout.theme;
+    let level = theme.level(record.level());
+
+    // module path and source location, painted in the module style
+    let mut place = Vec::new();
+    if let Some(module_path) = record.module_path() {
+        place.push(module_path.to_string());
+    }
+    if ctx.layout.source_location {
+        match (record.file(), record.line()) {
+            (Some(file), Some(line)) => place.push(format!("{}:{}", file, line)),
+            (Some(file), None) => place.push(file.to_string()),
+            _ => {}
+        }
+    }
+    let place = place.join(" ");
+
+    let mut head = format!("[{} {}", level.label, time);
+    if ctx.layout.thread_name {
+        head.push(' ');
+        head.push_str(&thread_name());
+    }
+    let header = if place.is_empty() {
+        format!("{}]", head)
+    } else {
+        format!("{} {}]", head, place)
+    };
+
+    match (ctx.layout.color, theme.module) {
+        (false, _) => out.push_str(&header),
+        (true, Some(module)) if !place.is_empty() => {
+            let _ = write!(out, "{}{}{}",
+                level.style.paint(&format!("{} ", head)),
+                module.paint(&place),
+                level.style.paint("]"));
+        }
+        (true, _) => out.push_str(&level.style.paint(&header)),
+    }
+
+    let width = header.chars().count();
+    let _ = write!(out, " {}",
+        format!("{}", record.args()).replace("\n", &format!("\n{: <width$} ", " ", width=width)));
+    for (key, value) in ctx.fields(record) {
+        logfmt::pair(out, &key, &value.to_string());
+    }
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use log::Level;
+
+    use format::{Color, Layout, Style, Theme};
+
+    fn render(layout: &Layout, message: &str) -> String {
+        let ctx = Context {
+            start: UNIX_EPOCH,
+            now: UNIX_EPOCH + Duration::from_millis(1_500),
+            layout,
+            trace: None,
+            mdc: &Vec::new(),
+        };
+        let mut out = String::new();
+        write(&Record::builder()
+            .args(format_args!("{}", message))
+            .level(Level::Warn)
+            .module_path(Some("app"))
+            .file(Some("src/app.rs"))
+            .line(Some(9))
+            .key_values(&[("id", 7)])
+            .build(), &ctx, &mut out);
+        out
+    }
+
+    #[test]
+    fn uses_theme_labels_and_styles() {
+        let mut layout = Layout {
+            theme: Theme::high_contrast(),
+            ..Layout::default()
+        };
+        assert_eq!(render(&layout, "a\nb"), "[WARN  0:00:01.500 app] a\n                        b id=7\n");
+
+        layout.color = true;
+        layout.theme.module = Some(Style::new().fg(Color::Cyan));
+        assert_eq!(render(&layout, "a"), concat!(
+            "\x1b[1;43;30m[WARN  0:00:01.500 \x1b[0m",
+            "\x1b[36mapp\x1b[0m",
+            "\x1b[1;43;30m]\x1b[0m a id=7\n",
+        ));
+
+        layout.color = false;
+        layout.source_location = true;
+        assert_eq!(render(&layout, "a\nb"), "[WARN  0:00:01.500 app src/app.rs:9] a\n                                     b id=7\n");
+    }
+
+    #[test]
+    fn shows_the_thread_name() {
+        let layout = Layout {
+            theme: Theme::high_contrast(),
+            thread_name: true,
+            ..Layout::default()
+        };
+
+        // continuation lines line up with the message whatever the thread
+        let out = thread::Builder::new().name("wörker-1".into())
+            .spawn(move || render(&layout, "a\nb")).unwrap().join().unwrap();
+        assert_eq!(out, "[WARN  0:00:01.500 wörker-1 app] a\n                                 b id=7\n");
+    }
+}
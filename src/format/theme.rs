@@ -0,0 +1,175 @@
+use ansi_term;
+use log::Level;
+
+/// A terminal color.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Purple,
+    Cyan,
+    White,
+    /// One of the 256 indexed colors.
+    Fixed(u8),
+    /// A 24-bit color.
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    fn ansi(self) -> ansi_term::Colour {
+        match self {
+            Color::Black => ansi_term::Colour::Black,
+            Color::Red => ansi_term::Colour::Red,
+            Color::Green => ansi_term::Colour::Green,
+            Color::Yellow => ansi_term::Colour::Yellow,
+            Color::Blue => ansi_term::Colour::Blue,
+            Color::Purple => ansi_term::Colour::Purple,
+            Color::Cyan => ansi_term::Colour::Cyan,
+            Color::White => ansi_term::Colour::White,
+            Color::Fixed(n) => ansi_term::Colour::Fixed(n),
+            Color::Rgb(r, g, b) => ansi_term::Colour::RGB(r, g, b),
+        }
+    }
+}
+
+/// How a piece of the header is painted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+}
+
+impl Style {
+    pub fn new() -> Style {
+        Style::default()
+    }
+
+    pub fn fg(mut self, color: Color) -> Style {
+        self.fg = Some(color);
+        self
+    }
+
+    pub fn bg(mut self, color: Color) -> Style {
+        self.bg = Some(color);
+        self
+    }
+
+    pub fn bold(mut self) -> Style {
+        self.bold = true;
+        self
+    }
+
+    pub fn dim(mut self) -> Style {
+        self.dim = true;
+        self
+    }
+
+    pub(crate) fn paint(&self, text: &str) -> String {
+        let mut style = ansi_term::Style::new();
+        if let Some(fg) = self.fg {
+            style = style.fg(fg.ansi());
+        }
+        if let Some(bg) = self.bg {
+            style = style.on(bg.ansi());
+        }
+        if self.bold {
+            style = style.bold();
+        }
+        if self.dim {
+            style = style.dimmed();
+        }
+        style.paint(text).to_string()
+    }
+}
+
+/// The label and style of one level.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LevelTheme {
+    pub label: String,
+    pub style: Style,
+}
+
+impl LevelTheme {
+    pub fn new(label: &str, style: Style) -> LevelTheme {
+        LevelTheme {
+            label: label.to_string(),
+            style,
+        }
+    }
+}
+
+/// Labels and colors of the pretty console header.
+///
+/// The fields are public so a theme can be written out as a plain struct
+/// literal or adjusted from one of the presets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Theme {
+    pub trace: LevelTheme,
+    pub debug: LevelTheme,
+    pub info: LevelTheme,
+    pub warn: LevelTheme,
+    pub error: LevelTheme,
+    /// Style of the module path; `None` paints it like the rest of the
+    /// header.
+    pub module: Option<Style>,
+}
+
+impl Theme {
+    /// The default: bold level colors over the whole header.
+    pub fn funky() -> Theme {
+        Theme {
+            trace: LevelTheme::new("TRC", Style::new().fg(Color::Purple).bold()),
+            debug: LevelTheme::new("DBG", Style::new().fg(Color::Blue).bold()),
+            info: LevelTheme::new("LOG", Style::new().fg(Color::Green).bold()),
+            warn: LevelTheme::new("WRN", Style::new().fg(Color::Yellow).bold()),
+            error: LevelTheme::new("ERR", Style::new().fg(Color::Red).bold()),
+            module: None,
+        }
+    }
+
+    /// Black or white text on solid backgrounds, for light terminals and
+    /// poor eyesight alike.
+    pub fn high_contrast() -> Theme {
+        Theme {
+            trace: LevelTheme::new("TRACE", Style::new().fg(Color::White).bg(Color::Purple).bold()),
+            debug: LevelTheme::new("DEBUG", Style::new().fg(Color::White).bg(Color::Blue).bold()),
+            info: LevelTheme::new("INFO ", Style::new().fg(Color::Black).bg(Color::Green).bold()),
+            warn: LevelTheme::new("WARN ", Style::new().fg(Color::Black).bg(Color::Yellow).bold()),
+            error: LevelTheme::new("ERROR", Style::new().fg(Color::White).bg(Color::Red).bold()),
+            module: Some(Style::new().bold()),
+        }
+    }
+
+    /// No colors, with the less important levels dimmed.
+    pub fn monochrome() -> Theme {
+        Theme {
+            trace: LevelTheme::new("TRC", Style::new().dim()),
+            debug: LevelTheme::new("DBG", Style::new().dim()),
+            info: LevelTheme::new("LOG", Style::new()),
+            warn: LevelTheme::new("WRN", Style::new().bold()),
+            error: LevelTheme::new("ERR", Style::new().bold()),
+            module: None,
+        }
+    }
+
+    pub fn level(&self, level: Level) -> &LevelTheme {
+        match level {
+            Level::Trace => &self.trace,
+            Level::Debug => &self.debug,
+            Level::Info => &self.info,
+            Level::Warn => &self.warn,
+            Level::Error => &self.error,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::funky()
+    }
+}
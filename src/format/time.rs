@@ -0,0 +1,108 @@
+use std::fmt::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+
+/// Which clock the console timestamp shows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Timestamp {
+    /// Time since the logger was built, as `h:mm:ss.fff`.
+    #[default]
+    Uptime,
+    /// RFC 3339 in UTC, e.g. `2023-11-14T22:13:20.123Z`.
+    Utc,
+    /// RFC 3339 with the local offset, e.g. `2023-11-15T00:13:20.123+02:00`.
+    Local,
+    /// Seconds since the Unix epoch, e.g. `1700000000.123`.
+    Unix,
+}
+
+/// How many fractional digits timestamps get.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Precision {
+    Seconds,
+    #[default]
+    Millis,
+    Micros,
+    Nanos,
+}
+
+impl Precision {
+    fn digits(self) -> usize {
+        match self {
+            Precision::Seconds => 0,
+            Precision::Millis => 3,
+            Precision::Micros => 6,
+            Precision::Nanos => 9,
+        }
+    }
+
+    fn seconds_format(self) -> SecondsFormat {
+        match self {
+            Precision::Seconds => SecondsFormat::Secs,
+            Precision::Millis => SecondsFormat::Millis,
+            Precision::Micros => SecondsFormat::Micros,
+            Precision::Nanos => SecondsFormat::Nanos,
+        }
+    }
+
+    /// Appends `.` and the truncated fraction of `nanos`, if any digits.
+    fn fraction_to(self, out: &mut String, nanos: u32) {
+        let digits = self.digits();
+        if digits > 0 {
+            let _ = write!(out, ".{:0width$}", nanos / 10u32.pow(9 - digits as u32), width = digits);
+        }
+    }
+}
+
+/// Formats `now` in the given mode, `start` being when the logger was built.
+pub(crate) fn format(mode: Timestamp, precision: Precision, start: SystemTime, now: SystemTime) -> String {
+    match mode {
+        Timestamp::Uptime => uptime_with(elapsed(start, now), precision),
+        Timestamp::Utc => DateTime::<Utc>::from(now).to_rfc3339_opts(precision.seconds_format(), true),
+        Timestamp::Local => DateTime::<Local>::from(now).to_rfc3339_opts(precision.seconds_format(), false),
+        Timestamp::Unix => {
+            let d = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+            let mut out = d.as_secs().to_string();
+            precision.fraction_to(&mut out, d.subsec_nanos());
+            out
+        }
+    }
+}
+
+fn elapsed(start: SystemTime, time: SystemTime) -> Duration {
+    match time.duration_since(start) {
+        Ok(d) => d,
+        Err(e) => e.duration(),
+    }
+}
+
+fn uptime_with(d: Duration, precision: Precision) -> String {
+    let secs = d.as_secs() % 60;
+    let mins = d.as_secs() / 60 % 60;
+    let hours = d.as_secs() / 3600;
+    let mut out = format!("{}:{:02}:{:02}", hours, mins, secs);
+    precision.fraction_to(&mut out, d.subsec_nanos());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_every_mode() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let now = start + Duration::new(3723, 123_456_789);
+
+        assert_eq!(format(Timestamp::Uptime, Precision::Millis, start, now), "1:02:03.123");
+        assert_eq!(format(Timestamp::Uptime, Precision::Seconds, start, now), "1:02:03");
+        assert_eq!(format(Timestamp::Utc, Precision::Micros, start, now), "2023-11-14T23:15:23.123456Z");
+        assert_eq!(format(Timestamp::Utc, Precision::Seconds, start, now), "2023-11-14T23:15:23Z");
+        assert_eq!(format(Timestamp::Unix, Precision::Nanos, start, now), "1700003723.123456789");
+        assert_eq!(format(Timestamp::Unix, Precision::Seconds, start, now), "1700003723");
+
+        let local = format(Timestamp::Local, Precision::Nanos, start, now);
+        assert_eq!(DateTime::parse_from_rfc3339(&local).unwrap(), DateTime::<Utc>::from(now));
+    }
+}
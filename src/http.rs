@@ -0,0 +1,425 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use ureq::Agent;
+
+use config::DatadogConfig;
+use error::Error;
+use logs::LogEncoder;
+use record::{OwnedRecord, Sink};
+use stats;
+
+/// Settings for sending logs to the Datadog Logs HTTP intake.
+///
+/// Records are batched as JSON arrays and posted once a batch is full or
+/// its first entry has waited for the linger interval. Each entry carries `message`, `status`,
+/// `service`, `ddsource`, `ddtags`, `hostname`, `logger.name` and
+/// `timestamp`.
+///
+/// ```no_run
+/// use funky_logger::{DatadogConfig, HttpLogsConfig};
+///
+/// let config = DatadogConfig::new()
+///     .service("billing")
+///     .events(false)
+///     .http_logs(HttpLogsConfig::new().api_key("<DD_API_KEY>"));
+/// funky_logger::formatted_builder_with(config).unwrap().init();
+/// ```
+#[derive(Clone)]
+pub struct HttpLogsConfig {
+    url: Option<String>,
+    api_key: Option<String>,
+    source: String,
+    gzip: bool,
+    max_payload_bytes: usize,
+    max_batch_len: usize,
+    timeout: Duration,
+    linger: Duration,
+}
+
+impl Default for HttpLogsConfig {
+    fn default() -> Self {
+        HttpLogsConfig {
+            url: None,
+            api_key: None,
+            source: "rust".to_string(),
+            gzip: true,
+            // the intake's own limits
+            max_payload_bytes: 5_000_000,
+            max_batch_len: 1000,
+            timeout: Duration::from_secs(10),
+            linger: Duration::from_secs(1),
+        }
+    }
+}
+
+impl fmt::Debug for HttpLogsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // the API key stays out of logs and panic messages
+        f.debug_struct("HttpLogsConfig")
+            .field("url", &self.url)
+            .field("api_key", &self.api_key.as_ref().map(|_| "<redacted>"))
+            .field("source", &self.source)
+            .field("gzip", &self.gzip)
+            .field("max_payload_bytes", &self.max_payload_bytes)
+            .field("max_batch_len", &self.max_batch_len)
+            .field("timeout", &self.timeout)
+            .field("linger", &self.linger)
+            .finish()
+    }
+}
+
+impl HttpLogsConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Where logs are posted. Defaults to the intake for `DD_SITE`, or
+    /// `https://http-intake.logs.datadoghq.com/api/v2/logs`.
+    pub fn url<S: Into<String>>(mut self, url: S) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    /// The API key sent in `DD-API-KEY`. Defaults to `DD_API_KEY`; building
+    /// the logger fails without one.
+    pub fn api_key<S: Into<String>>(mut self, api_key: S) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    /// The `ddsource` of every entry. Defaults to `rust`.
+    pub fn source<S: Into<String>>(mut self, source: S) -> Self {
+        self.source = source.into();
+        self
+    }
+
+    /// Whether payloads are gzip compressed. On by default.
+    pub fn gzip(mut self, gzip: bool) -> Self {
+        self.gzip = gzip;
+        self
+    }
+
+    /// Largest uncompressed payload to post. Defaults to 5MB, the intake's
+    /// limit. Entries that don't fit on their own are dropped.
+    pub fn max_payload_bytes(mut self, bytes: usize) -> Self {
+        self.max_payload_bytes = bytes;
+        self
+    }
+
+    /// Most entries to post at once. Defaults to 1000, the intake's limit.
+    pub fn max_batch_len(mut self, len: usize) -> Self {
+        self.max_batch_len = len.max(1);
+        self
+    }
+
+    /// How long a single request may take. Defaults to 10 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How long a batch may wait for more entries before it is posted.
+    /// Defaults to one second. Flushing posts right away.
+    pub fn linger(mut self, linger: Duration) -> Self {
+        self.linger = linger;
+        self
+    }
+
+    pub(crate) fn with_env<F>(mut self, var: F) -> Self
+        where F: Fn(&str) -> Option<String>
+    {
+        self.api_key = self.api_key.or_else(|| var("DD_API_KEY"));
+        if self.url.is_none() {
+            let site = var("DD_SITE").unwrap_or_else(|| "datadoghq.com".to_string());
+            self.url = Some(format!("https://http-intake.logs.{}/api/v2/logs", site));
+        }
+        self
+    }
+}
+
+/// Batches records as JSON and posts them to the Logs intake.
+pub(crate) struct HttpSink {
+    agent: Agent,
+    url: String,
+    api_key: String,
+    gzip: bool,
+    max_payload_bytes: usize,
+    max_batch_len: usize,
+    linger: Duration,
+    encoder: LogEncoder,
+    batch: Vec<String>,
+    // size of the batch as a JSON array
+    batch_bytes: usize,
+    // when the first entry of the batch arrived
+    batch_started: Option<Instant>,
+}
+
+impl HttpSink {
+    pub fn new(logs: &HttpLogsConfig, config: &DatadogConfig, tags: &[String])
+        -> Result<HttpSink, Error>
+    {
+        let api_key = logs.api_key.clone()
+            .ok_or_else(|| Error::Config("the Datadog Logs intake needs an API key".into()))?;
+        let url = logs.url.clone()
+            .unwrap_or_else(|| "https://http-intake.logs.datadoghq.com/api/v2/logs".into());
+
+        Ok(HttpSink {
+            agent: ureq::AgentBuilder::new().timeout(logs.timeout).build(),
+            url,
+            api_key,
+            gzip: logs.gzip,
+            max_payload_bytes: logs.max_payload_bytes,
+            max_batch_len: logs.max_batch_len,
+            linger: logs.linger,
+            encoder: LogEncoder::new(&logs.source, config, tags),
+            batch: Vec::new(),
+            batch_bytes: 2,
+            batch_started: None,
+        })
+    }
+
+    /// Posts the batch. Returns whether it was accepted.
+    fn post(&mut self) -> bool {
+        if self.batch.is_empty() {
+            return true;
+        }
+
+        let records = self.batch.len() as u64;
+        let mut body = String::with_capacity(self.batch_bytes);
+        body.push('[');
+        body.push_str(&self.batch.join(","));
+        body.push(']');
+        self.batch.clear();
+        self.batch_bytes = 2;
+        self.batch_started = None;
+
+        match self.request(body.as_bytes()) {
+            Ok(()) => {
+                stats::record_sent(records);
+                true
+            }
+            Err(e) => {
+                stats::record_send_error(&e, records);
+                false
+            }
+        }
+    }
+
+    fn request(&self, body: &[u8]) -> io::Result<()> {
+        let request = self.agent.post(&self.url)
+            .set("DD-API-KEY", &self.api_key)
+            .set("Content-Type", "application/json");
+
+        let result = if self.gzip {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(body)?;
+            request.set("Content-Encoding", "gzip").send_bytes(&encoder.finish()?)
+        } else {
+            request.send_bytes(body)
+        };
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(status, _)) => Err(io::Error::other(
+                format!("logs intake responded with HTTP {}", status)
+            )),
+            Err(e) => Err(io::Error::other(e.to_string())),
+        }
+    }
+}
+
+impl Sink for HttpSink {
+    fn send(&mut self, record: &OwnedRecord) {
+        let entry = self.encoder.encode(record);
+
+        // one more entry adds its own length and a comma
+        if entry.len() + 2 > self.max_payload_bytes {
+            stats::record_send_error(&io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("log entry of {} bytes is larger than the maximum payload", entry.len()),
+            ), 1);
+            return;
+        }
+        if self.batch_bytes + entry.len() + 1 > self.max_payload_bytes {
+            self.post();
+        }
+
+        self.batch_bytes += entry.len() + if self.batch.is_empty() { 0 } else { 1 };
+        self.batch.push(entry);
+        self.batch_started.get_or_insert_with(Instant::now);
+        if self.batch.len() >= self.max_batch_len {
+            self.post();
+        }
+    }
+
+    fn idle(&mut self) -> Option<Duration> {
+        let waited = self.batch_started?.elapsed();
+        if waited >= self.linger {
+            self.post();
+            None
+        } else {
+            Some(self.linger - waited)
+        }
+    }
+
+    fn flush(&mut self) -> bool {
+        self.post()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    use flate2::read::GzDecoder;
+    use log::{Level, Record};
+
+    use datadog::DatadogSink;
+
+    struct Request {
+        headers: Vec<String>,
+        body: Vec<u8>,
+    }
+
+    /// A stand-in intake answering `requests` requests with 202.
+    fn intake(requests: usize) -> (String, mpsc::Receiver<Request>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/api/v2/logs", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut headers = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    headers.push(line.trim().to_lowercase());
+                }
+                let len = headers.iter()
+                    .find_map(|h| h.strip_prefix("content-length:"))
+                    .map_or(0, |len| len.trim().parse().unwrap());
+                let mut body = vec![0; len];
+                reader.read_exact(&mut body).unwrap();
+
+                stream.write_all(b"HTTP/1.1 202 Accepted\r\nContent-Length: 2\r\n\r\n{}").unwrap();
+                tx.send(Request { headers, body }).unwrap();
+            }
+        });
+
+        (url, rx)
+    }
+
+    fn record(level: Level, message: &str) -> OwnedRecord {
+        OwnedRecord::test(level, "app::db", message)
+    }
+
+    fn sink(logs: HttpLogsConfig) -> HttpSink {
+        let config = DatadogConfig::new().service("billing").hostname("web-1");
+        HttpSink::new(&logs.api_key("secret"), &config, &["env:test".into()]).unwrap()
+    }
+
+    #[test]
+    fn posts_gzipped_json_batches() {
+        let (url, requests) = intake(1);
+        let mut sink = sink(HttpLogsConfig::new().url(url));
+
+        sink.send(&record(Level::Error, "connection lost"));
+        sink.send(&record(Level::Info, "say \"hi\""));
+        sink.flush();
+
+        let request = requests.recv().unwrap();
+        assert!(request.headers[0].starts_with("post /api/v2/logs "));
+        assert!(request.headers.contains(&"dd-api-key: secret".to_string()));
+        assert!(request.headers.contains(&"content-encoding: gzip".to_string()));
+
+        let mut body = String::new();
+        GzDecoder::new(&request.body[..]).read_to_string(&mut body).unwrap();
+        assert_eq!(body, concat!(
+            r#"[{"message":"connection lost","status":"error","ddsource":"rust","service":"billing","#,
+            r#""hostname":"web-1","ddtags":"env:test","timestamp":1700000000123,"logger":{"name":"app::db","thread_name":"main"}},"#,
+            r#"{"message":"say \"hi\"","status":"info","ddsource":"rust","service":"billing","#,
+            r#""hostname":"web-1","ddtags":"env:test","timestamp":1700000000123,"logger":{"name":"app::db","thread_name":"main"}}]"#,
+        ));
+    }
+
+    #[test]
+    fn splits_batches_by_length_and_size() {
+        let (url, requests) = intake(3);
+        let mut sink = sink(HttpLogsConfig::new().url(url).gzip(false).max_batch_len(2).max_payload_bytes(500));
+
+        let before = stats::stats().send_errors;
+        sink.send(&record(Level::Info, "one"));
+        sink.send(&record(Level::Info, "two"));
+        sink.send(&record(Level::Info, &"x".repeat(500)));
+        sink.send(&record(Level::Info, &"y".repeat(200)));
+        sink.send(&record(Level::Info, &"z".repeat(200)));
+        sink.flush();
+        assert!(stats::stats().send_errors > before);
+
+        let count = |request: Request| {
+            let body = String::from_utf8(request.body).unwrap();
+            assert!(body.len() <= 500);
+            body.matches("\"message\"").count()
+        };
+        // split by length, then twice by size with the oversized entry dropped
+        assert_eq!(count(requests.recv().unwrap()), 2);
+        assert_eq!(count(requests.recv().unwrap()), 1);
+        assert_eq!(count(requests.recv().unwrap()), 1);
+    }
+
+    #[test]
+    fn records_close_together_share_a_request() {
+        let (url, requests) = intake(2);
+        let logs = HttpLogsConfig::new().url(url).api_key("secret").gzip(false)
+            .linger(Duration::from_millis(300));
+        let sink = DatadogSink::new(DatadogConfig::new().events(false).http_logs(logs)).unwrap();
+
+        let log = |message| sink.log(&Record::builder()
+            .args(format_args!("{}", message))
+            .level(Level::Info)
+            .build(), None, Vec::new());
+        log("one");
+        thread::sleep(Duration::from_millis(10));
+        log("two");
+
+        let request = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(String::from_utf8(request.body).unwrap().matches("\"message\"").count(), 2);
+        assert!(requests.recv_timeout(Duration::from_millis(500)).is_err());
+    }
+
+    #[test]
+    fn debug_redacts_the_api_key() {
+        let config = HttpLogsConfig::new().api_key("secret");
+        let debug = format!("{:?}", DatadogConfig::new().http_logs(config));
+        assert!(!debug.contains("secret"), "{}", debug);
+        assert!(debug.contains("api_key: Some(\"<redacted>\")"), "{}", debug);
+    }
+
+    #[test]
+    fn reports_rejected_payloads() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 4096];
+            let _ = stream.read(&mut buf);
+            stream.write_all(b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n").unwrap();
+        });
+
+        let sink = sink(HttpLogsConfig::new().url(url));
+        assert!(sink.request(b"[]").unwrap_err().to_string().contains("403"));
+    }
+}
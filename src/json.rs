@@ -0,0 +1,88 @@
+use std::fmt::Write;
+
+/// Writes a JSON object field by field.
+pub(crate) struct Object<'a> {
+    out: &'a mut String,
+    first: bool,
+}
+
+impl<'a> Object<'a> {
+    pub fn new(out: &'a mut String) -> Object<'a> {
+        out.push('{');
+        Object { out, first: true }
+    }
+
+    fn key(&mut self, key: &str) {
+        if !self.first {
+            self.out.push(',');
+        }
+        self.first = false;
+        string(self.out, key);
+        self.out.push(':');
+    }
+
+    pub fn string(&mut self, key: &str, value: &str) -> &mut Self {
+        self.key(key);
+        string(self.out, value);
+        self
+    }
+
+    pub fn number(&mut self, key: &str, value: u64) -> &mut Self {
+        self.key(key);
+        let _ = write!(self.out, "{}", value);
+        self
+    }
+
+    /// Writes `value` as is; it must already be valid JSON.
+    pub fn raw(&mut self, key: &str, value: &str) -> &mut Self {
+        self.key(key);
+        self.out.push_str(value);
+        self
+    }
+
+    /// Starts a nested object. Finish it before writing the next field.
+    pub fn object(&mut self, key: &str) -> Object<'_> {
+        self.key(key);
+        Object::new(self.out)
+    }
+
+    pub fn finish(&mut self) {
+        self.out.push('}');
+    }
+}
+
+/// Writes `s` as a quoted JSON string.
+pub(crate) fn string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_nested_objects() {
+        let mut out = String::new();
+        {
+            let mut obj = Object::new(&mut out);
+            obj.string("message", "say \"hi\"\n\\o/\u{1}").number("n", 42);
+            obj.object("logger").string("name", "app").finish();
+            obj.string("status", "info").finish();
+        }
+        assert_eq!(out, r#"{"message":"say \"hi\"\n\\o/\u0001","n":42,"logger":{"name":"app"},"status":"info"}"#);
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt;
+
+#[cfg(feature = "tracing")]
+use log::kv::{Source, ToValue};
+use log::kv::{self, Key, VisitSource};
+use log::Record;
+
+use json::Object;
+
+/// An owned key-value from a record's `log::kv` source.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+    Bool(bool),
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    Str(String),
+}
+
+impl Value {
+    pub fn new(value: &kv::Value) -> Value {
+        if let Some(b) = value.to_bool() {
+            Value::Bool(b)
+        } else if let Some(n) = value.to_u64() {
+            Value::Uint(n)
+        } else if let Some(n) = value.to_i64() {
+            Value::Int(n)
+        } else if let Some(n) = value.to_f64() {
+            Value::Float(n)
+        } else {
+            Value::Str(value.to_string())
+        }
+    }
+
+    /// Writes the value as a JSON field, keeping numbers and booleans
+    /// unquoted.
+    pub fn write_json(&self, obj: &mut Object, key: &str) {
+        match *self {
+            Value::Bool(b) => obj.raw(key, if b { "true" } else { "false" }),
+            Value::Int(n) => obj.raw(key, &n.to_string()),
+            Value::Uint(n) => obj.number(key, n),
+            Value::Float(n) if n.is_finite() => obj.raw(key, &n.to_string()),
+            ref v => obj.string(key, &v.to_string()),
+        };
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Bool(b) => b.fmt(f),
+            Value::Int(n) => n.fmt(f),
+            Value::Uint(n) => n.fmt(f),
+            Value::Float(n) => n.fmt(f),
+            Value::Str(ref s) => s.fmt(f),
+        }
+    }
+}
+
+/// Key-values in the order they were logged.
+pub(crate) type Fields = Vec<(String, Value)>;
+
+/// Replaces the value of `key`, or appends it.
+pub(crate) fn set(fields: &mut Fields, key: String, value: Value) {
+    match fields.iter_mut().find(|(k, _)| *k == key) {
+        Some(field) => field.1 = value,
+        None => fields.push((key, value)),
+    }
+}
+
+/// `base` with the key-values of `record` set over it.
+pub(crate) fn merge(mut base: Fields, record: &Record) -> Fields {
+    if base.is_empty() {
+        return collect(record);
+    }
+    for (key, value) in collect(record) {
+        set(&mut base, key, value);
+    }
+    base
+}
+
+/// Copies the key-values of `record`.
+pub(crate) fn collect(record: &Record) -> Fields {
+    struct Collect(Fields);
+
+    impl<'kvs> VisitSource<'kvs> for Collect {
+        fn visit_pair(&mut self, key: Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+            self.0.push((key.as_str().to_string(), Value::new(&value)));
+            Ok(())
+        }
+    }
+
+    let mut collect = Collect(Vec::new());
+    let _ = record.key_values().visit(&mut collect);
+    collect.0
+}
+
+/// Lends collected fields back to a `log::Record`.
+#[cfg(feature = "tracing")]
+pub(crate) struct FieldSource<'a>(pub &'a Fields);
+
+#[cfg(feature = "tracing")]
+impl<'a> Source for FieldSource<'a> {
+    fn visit<'kvs>(&'kvs self, visitor: &mut dyn VisitSource<'kvs>) -> Result<(), kv::Error> {
+        for (key, value) in self.0 {
+            visitor.visit_pair(Key::from_str(key), value.to_value())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl ToValue for Value {
+    fn to_value(&self) -> kv::Value<'_> {
+        match *self {
+            Value::Bool(b) => kv::Value::from(b),
+            Value::Int(n) => kv::Value::from(n),
+            Value::Uint(n) => kv::Value::from(n),
+            Value::Float(n) => kv::Value::from(n),
+            Value::Str(ref s) => kv::Value::from(s.as_str()),
+        }
+    }
+}
+
+/// The JSON key for a field, prefixed with `kv.` if it would clash with one
+/// of the `reserved` attributes.
+pub(crate) fn json_key(key: &str, reserved: &[&str]) -> String {
+    if reserved.contains(&key) {
+        format!("kv.{}", key)
+    } else {
+        key.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_value_types() {
+        let fields = collect(&Record::builder()
+            .key_values(&[
+                ("ok", kv::Value::from(true)),
+                ("n", kv::Value::from(-3i64)),
+                ("size", kv::Value::from(4096u64)),
+                ("ratio", kv::Value::from(0.5f64)),
+                ("user", kv::Value::from("ann")),
+            ])
+            .build());
+
+        assert_eq!(fields, vec![
+            ("ok".to_string(), Value::Bool(true)),
+            ("n".to_string(), Value::Int(-3)),
+            ("size".to_string(), Value::Uint(4096)),
+            ("ratio".to_string(), Value::Float(0.5)),
+            ("user".to_string(), Value::Str("ann".to_string())),
+        ]);
+
+        let mut out = String::new();
+        {
+            let mut obj = Object::new(&mut out);
+            for (key, value) in &fields {
+                value.write_json(&mut obj, key);
+            }
+            obj.finish();
+        }
+        assert_eq!(out, r#"{"ok":true,"n":-3,"size":4096,"ratio":0.5,"user":"ann"}"#);
+    }
+}
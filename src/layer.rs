@@ -0,0 +1,221 @@
+use std::fmt;
+use std::sync::Arc;
+
+use log::{Level, Log, Record};
+use tracing_core::field::{Field, Visit};
+use tracing_core::span::{Attributes, Id, Record as SpanRecord};
+use tracing_core::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use kv::{set, FieldSource, Fields, Value};
+use logger::FunkyLogger;
+
+/// A `tracing_subscriber::Layer` that hands events to a `FunkyLogger`, so
+/// they get the same console layout and go to the same Datadog outputs as
+/// records from `log`.
+///
+/// Fields of the event and of every span it's in become key-values of the
+/// record, with the innermost value winning when names repeat. On Datadog
+/// they are attributes, or tags for keys given to `DatadogConfig::tag_key`.
+///
+/// Built with `Builder::layer`, or with `Builder::init_layer` when `log`
+/// records should go through the same logger; the builder's filter still
+/// applies. Each logger has a Datadog sender of its own, so a program using
+/// both `log` and `tracing` should use `init_layer` rather than two
+/// builders.
+///
+/// ```no_run
+/// # extern crate funky_logger;
+/// # extern crate tracing_subscriber;
+/// use tracing_subscriber::layer::SubscriberExt;
+///
+/// # fn main() {
+/// let layer = funky_logger::formatted_builder().unwrap().parse("info").init_layer();
+/// let subscriber = tracing_subscriber::registry().with(layer);
+/// # let _ = subscriber;
+/// # }
+/// ```
+pub struct FunkyLayer {
+    logger: Arc<FunkyLogger>,
+}
+
+impl FunkyLayer {
+    pub fn new(logger: FunkyLogger) -> FunkyLayer {
+        FunkyLayer::shared(Arc::new(logger))
+    }
+
+    pub(crate) fn shared(logger: Arc<FunkyLogger>) -> FunkyLayer {
+        FunkyLayer { logger }
+    }
+}
+
+impl From<FunkyLogger> for FunkyLayer {
+    fn from(logger: FunkyLogger) -> FunkyLayer {
+        FunkyLayer::new(logger)
+    }
+}
+
+/// Fields recorded on a span so far, kept in its extensions.
+struct SpanFields(Fields);
+
+impl<S> Layer<S> for FunkyLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes, id: &Id, ctx: Context<S>) {
+        let mut visitor = Visitor::default();
+        attrs.record(&mut visitor);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(visitor.fields));
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &SpanRecord, ctx: Context<S>) {
+        let span = match ctx.span(id) {
+            Some(span) => span,
+            None => return,
+        };
+        let mut visitor = Visitor::default();
+        values.record(&mut visitor);
+
+        let mut extensions = span.extensions_mut();
+        match extensions.get_mut::<SpanFields>() {
+            Some(SpanFields(fields)) => {
+                for (key, value) in visitor.fields {
+                    set(fields, key, value);
+                }
+            }
+            None => extensions.insert(SpanFields(visitor.fields)),
+        }
+    }
+
+    fn on_event(&self, event: &Event, ctx: Context<S>) {
+        let metadata = event.metadata();
+        let level = level(*metadata.level());
+        if !self.logger.enabled(&log::Metadata::builder().level(level).target(metadata.target()).build()) {
+            return;
+        }
+
+        // outermost span first, so inner spans and the event override it
+        let mut fields = Fields::new();
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                if let Some(SpanFields(span_fields)) = span.extensions().get::<SpanFields>() {
+                    for (key, value) in span_fields {
+                        set(&mut fields, key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        let mut visitor = Visitor::default();
+        event.record(&mut visitor);
+        for (key, value) in visitor.fields {
+            set(&mut fields, key, value);
+        }
+
+        let source = FieldSource(&fields);
+        self.logger.log(&Record::builder()
+            .args(format_args!("{}", visitor.message.unwrap_or_default()))
+            .level(level)
+            .target(metadata.target())
+            .module_path(metadata.module_path())
+            .file(metadata.file())
+            .line(metadata.line())
+            .key_values(&source)
+            .build());
+    }
+}
+
+fn level(level: tracing_core::Level) -> Level {
+    match level {
+        tracing_core::Level::TRACE => Level::Trace,
+        tracing_core::Level::DEBUG => Level::Debug,
+        tracing_core::Level::INFO => Level::Info,
+        tracing_core::Level::WARN => Level::Warn,
+        tracing_core::Level::ERROR => Level::Error,
+    }
+}
+
+#[derive(Default)]
+struct Visitor {
+    message: Option<String>,
+    fields: Fields,
+}
+
+impl Visitor {
+    fn push(&mut self, field: &Field, value: Value) {
+        if field.name() == "message" {
+            self.message = Some(value.to_string());
+        } else {
+            self.fields.push((field.name().to_string(), value));
+        }
+    }
+}
+
+impl Visit for Visitor {
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, Value::Bool(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field, Value::Int(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field, Value::Uint(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.push(field, Value::Float(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, Value::Str(value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.push(field, Value::Str(format!("{:?}", value)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    use tracing;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use config::DatadogConfig;
+
+    #[test]
+    fn sends_events_with_span_fields() {
+        let agent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        agent.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let config = DatadogConfig::new()
+            .host("127.0.0.1")
+            .port(agent.local_addr().unwrap().port())
+            .tag_key("user");
+
+        let mut builder = ::formatted_builder_with(config).unwrap();
+        let subscriber = ::tracing_subscriber::registry().with(builder.parse("warn").layer());
+        tracing::subscriber::with_default(subscriber, || {
+            let outer = tracing::info_span!("request", user = "ann", attempt = 1);
+            let _outer = outer.enter();
+            let inner = tracing::info_span!("query", attempt = 2);
+            let _inner = inner.enter();
+
+            tracing::info!("not sent");
+            tracing::warn!(rows = 0u64, "empty result");
+        });
+        ::flush();
+
+        let mut buf = [0; 1024];
+        let n = agent.recv(&mut buf).unwrap();
+        let datagram = String::from_utf8_lossy(&buf[..n]);
+        assert!(datagram.contains("|empty result attempt=2 rows=0|"), "{}", datagram);
+        assert!(datagram.contains("|t:warning|"), "{}", datagram);
+        assert!(datagram.ends_with(",user:ann"), "{}", datagram);
+    }
+}
@@ -1,133 +1,178 @@
-#[macro_use]
+#[cfg_attr(test, macro_use)]
 extern crate log;
 extern crate ansi_term;
+extern crate chrono;
 extern crate env_logger;
-extern crate dogstatsd;
-
-use std::fmt;
-use std::time::SystemTime;
-
-use ansi_term::{Color, Style};
-use env_logger::Builder;
-use log::Level;
-use dogstatsd::{Client, Options};
-
-struct DogLevel(Level);
-impl fmt::Display for DogLevel {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self.0 {
-            Level::Trace => "trace",
-            Level::Debug => "debug",
-            Level::Info => "info",
-            Level::Warn => "warning",
-            Level::Error => "error",
-        }.fmt(f)
-    }
-}
-
+#[cfg(feature = "http")]
+extern crate flate2;
+#[cfg(test)]
+extern crate tracing;
+#[cfg(feature = "tracing")]
+extern crate tracing_core;
+#[cfg(feature = "tracing")]
+extern crate tracing_subscriber;
+#[cfg(feature = "http")]
+extern crate ureq;
+
+mod config;
+mod console;
+mod datadog;
+mod encoder;
+mod error;
+mod event;
+mod format;
+#[cfg(feature = "http")]
+mod http;
+mod json;
+mod kv;
+#[cfg(feature = "tracing")]
+mod layer;
+mod limit;
+mod logger;
+mod logs;
+mod mdc;
+mod record;
+mod stats;
+mod tags;
+mod tcp;
+mod trace;
+mod transport;
+mod worker;
+
+pub use config::DatadogConfig;
+pub use env_logger::Target;
+pub use error::Error;
+pub use event::{AlertType, LevelMapping, Priority};
+pub use format::{
+    Color, ColorChoice, Format, LevelTheme, ParseFormatError, Precision, Style, Theme, Timestamp,
+};
+#[cfg(feature = "http")]
+pub use http::HttpLogsConfig;
+#[cfg(feature = "tracing")]
+pub use layer::FunkyLayer;
+pub use limit::{LimitBy, RateLimit};
+pub use logger::{Builder, FunkyLogger};
+pub use mdc::{InMdc, Mdc, MdcGuard};
+pub use stats::{stats, set_error_policy, ErrorPolicy, Stats};
+pub use tcp::TcpLogsConfig;
+pub use trace::{InTrace, LocalTrace, TraceContext, TraceGuard, TraceProvider};
+pub use worker::{flush, flush_guard, shutdown, FlushGuard, Overflow};
+
+use datadog::DatadogSink;
+
+/// Initializes the global logger.
+///
+/// Unlike `try_init`, this runs in degraded mode: if the Datadog client can't
+/// be set up, a warning is printed to stderr and logs only go to the console.
+///
+/// # Panics
+///
+/// Panics if a global logger was already set.
 #[inline]
 pub fn init() {
-    try_init().unwrap();
+    init_custom_env("RUST_LOG");
 }
 
-pub fn try_init() -> Result<(), log::SetLoggerError> {
+pub fn try_init() -> Result<(), Error> {
     try_init_custom_env("RUST_LOG")
 }
 
+/// Like `init`, but reads the filter from `environment_variable_name`.
 pub fn init_custom_env(environment_variable_name: &str) {
-    try_init_custom_env(environment_variable_name).unwrap();
+    let mut builder = or_console(formatted_builder());
+    parse_env(&mut builder, environment_variable_name);
+    builder.init();
 }
 
-pub fn try_init_custom_env(environment_variable_name: &str) -> Result<(), log::SetLoggerError> {
+pub fn try_init_custom_env(environment_variable_name: &str) -> Result<(), Error> {
     let mut builder = formatted_builder()?;
+    parse_env(&mut builder, environment_variable_name);
+    builder.try_init()?;
+    Ok(())
+}
 
+/// The builder, or a console-only one after printing why Datadog is out.
+fn or_console(builder: Result<Builder, Error>) -> Builder {
+    builder.unwrap_or_else(|e| {
+        eprintln!("funky_logger: {}, logging to console only", e);
+        console_builder()
+    })
+}
+
+fn parse_env(builder: &mut Builder, environment_variable_name: &str) {
     if let Ok(s) = ::std::env::var(environment_variable_name) {
         builder.parse(&s);
     }
+}
 
-    builder.try_init()
+/// Returns a builder that formats records for the console and sends them
+/// to Datadog with the default `DatadogConfig`.
+///
+/// Fails if the Datadog client can't be created. To keep console logging
+/// working anyway, fall back to `console_builder`:
+///
+/// ```no_run
+/// let mut builder = funky_logger::formatted_builder()
+///     .unwrap_or_else(|_| funky_logger::console_builder());
+/// builder.init();
+/// ```
+pub fn formatted_builder() -> Result<Builder, Error> {
+    formatted_builder_with(DatadogConfig::default())
 }
 
-pub fn formatted_builder() -> Result<Builder, log::SetLoggerError> {
-    let mut builder = Builder::new();
-
-    let mut opts = Options::default();
-    opts.namespace = "".to_string();
-    let dog = Client::new(opts).unwrap();
-
-    let start = SystemTime::now();
-
-    builder.format(move |f, record| {
-        use std::io::Write;
-
-        let now = SystemTime::now();
-        let d = match now.duration_since(start) {
-            Ok(d) => d,
-            Err(e) => e.duration(),
-        };
-
-        let secs = d.as_secs() % 60;
-        let mins = d.as_secs() / 60 % 60;
-        let hours = d.as_secs() / 3600;
-        let time = format!("{}:{:02}:{:02}.{:03}",
-            hours, mins, secs, d.subsec_nanos() / 1_000_000
-        );
-
-        let color = match record.level() {
-            Level::Trace => Color::Purple,
-            Level::Debug => Color::Blue,
-            Level::Info => Color::Green,
-            Level::Warn => Color::Yellow,
-            Level::Error => Color::Red,
-        };
-
-        let l = match record.level() {
-            Level::Trace => "TRC",
-            Level::Debug => "DBG",
-            Level::Info => "LOG",
-            Level::Warn => "WRN",
-            Level::Error => "ERR",
-        };
-
-        if let Some(module_path) = record.module_path() {
-
-            // our dirty datadog hack, maybe we shouldn't do it here
-            let tags = vec![
-                format!("level:{}", DogLevel(record.level())),
-                format!("module:{}", module_path),
-            ];
-            dog.event(format!("[{} {}] {}", l, time, module_path), format!("{}", record.args()), tags).unwrap();
-            
-            let header = format!("[{} {} {}]", l, time, module_path);
-            writeln!(f, "{} {}", 
-                Style::new().fg(color).bold().paint(header.clone()),
-                format!("{}",record.args()).replace("\n", &format!("\n{: <width$} ",  " ", width=header.len())))
-        } else {
-            let header = format!("[{} {}]", l, time);
-            writeln!(f, "{} {}", 
-                Style::new().fg(color).bold().paint(header.clone()),
-                format!("{}",record.args()).replace("\n", &format!("\n{: <width$} ",  " ", width=header.len())))
-        }
-    });
+/// Like `formatted_builder`, with explicit Datadog settings.
+///
+/// Events are handed to a background thread through a bounded queue, so
+/// logging never waits on the network. Use `flush` or `flush_guard` to make
+/// sure queued events are sent before the process exits.
+pub fn formatted_builder_with(config: DatadogConfig) -> Result<Builder, Error> {
+    Ok(Builder::new(Some(DatadogSink::new(config)?)))
+}
 
-    Ok(builder)
+/// Returns a builder with the same console output as `formatted_builder`,
+/// but without sending anything to Datadog.
+pub fn console_builder() -> Builder {
+    Builder::new(None)
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    // nothing on this machine has that address, so binding to it fails
+    fn unbindable() -> DatadogConfig {
+        DatadogConfig::new().bind_addr("10.255.255.1:0")
+    }
+
+    #[test]
+    fn bind_failures_are_datadog_errors() {
+        match formatted_builder_with(unbindable()) {
+            Err(Error::Datadog(_)) => {}
+            Err(e) => panic!("unexpected error: {:?}", e),
+            Ok(_) => panic!("bound to 10.255.255.1"),
+        }
+    }
+
+    #[test]
+    fn degrades_to_console_only() {
+        assert!(!or_console(formatted_builder_with(unbindable())).build().sends_to_datadog());
+        assert!(or_console(formatted_builder_with(DatadogConfig::new())).build().sends_to_datadog());
+    }
+
     #[test]
     fn log_types() {
         use init;
-        use std::thread::sleep_ms;
+        use std::thread::sleep;
+        use std::time::Duration;
         init();
         trace!("We are tracing now!");
-        sleep_ms(20);
+        sleep(Duration::from_millis(20));
         debug!("Debugging works fine");
-        sleep_ms(131);
+        sleep(Duration::from_millis(131));
         info!("This is an info level message: {}", 3 + 5);
-        sleep_ms(540);
+        sleep(Duration::from_millis(540));
         warn!("Warnings should be yellowish\nOh and by the way this is multiline");
-        sleep_ms(543);
+        sleep(Duration::from_millis(543));
         error!("Oofie owie this is serious");
     }
 }
\ No newline at end of file
@@ -0,0 +1,313 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::Record;
+
+use error::Error;
+use stats;
+
+/// What records share a rate limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LimitBy {
+    /// Each `file:line` that logs has its own limit.
+    #[default]
+    Callsite,
+    /// Each module path (or target, if there is none) has its own limit.
+    Module,
+}
+
+/// A token bucket limiting how many records are sent to Datadog.
+///
+/// Each callsite or module may send `burst` records at once, refilled
+/// evenly over `per`. Records over the limit are dropped, and once `per` has
+/// passed since the first of them, a warning saying how many were
+/// suppressed is sent in their place.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    burst: u32,
+    per: Duration,
+    by: LimitBy,
+}
+
+impl RateLimit {
+    /// At most `burst` records per `per`, e.g. `RateLimit::new(10,
+    /// Duration::from_secs(60))`. With `Duration::MAX` only `burst`
+    /// records are ever sent, and the rest are never summarized.
+    pub fn new(burst: u32, per: Duration) -> RateLimit {
+        RateLimit {
+            burst,
+            per,
+            by: LimitBy::default(),
+        }
+    }
+
+    /// What shares a limit. Defaults to `LimitBy::Callsite`.
+    pub fn by(mut self, by: LimitBy) -> RateLimit {
+        self.by = by;
+        self
+    }
+}
+
+/// What `Limiter::allow` decided about a record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Verdict {
+    Allow,
+    Suppress,
+    /// Suppressed, and a suppression window opened with it.
+    Opened,
+}
+
+/// Drops records before they're queued for Datadog, by rate limit and by
+/// sampling.
+pub(crate) struct Limiter {
+    limit: Option<RateLimit>,
+    sample_rate: f64,
+    state: Mutex<State>,
+}
+
+struct State {
+    buckets: HashMap<String, Bucket>,
+    // earliest time a suppression window closes
+    next_report: Option<Instant>,
+    rng: u64,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+    suppressed: u64,
+    since: Instant,
+}
+
+impl Limiter {
+    /// A limiter, or `None` if nothing would be dropped.
+    pub fn new(limit: Option<RateLimit>, sample_rate: f64) -> Result<Option<Limiter>, Error> {
+        if !(0.0..=1.0).contains(&sample_rate) {
+            return Err(Error::Config(format!("sample rate must be between 0 and 1, got {}", sample_rate)));
+        }
+        if let Some(limit) = limit {
+            if limit.burst == 0 || limit.per == Duration::from_secs(0) {
+                return Err(Error::Config(format!("invalid rate limit: {:?}", limit)));
+            }
+        }
+        if limit.is_none() && sample_rate == 1.0 {
+            return Ok(None);
+        }
+
+        Ok(Some(Limiter {
+            limit,
+            sample_rate,
+            state: Mutex::new(State {
+                buckets: HashMap::new(),
+                next_report: None,
+                // `RandomState` is seeded randomly per process
+                rng: RandomState::new().build_hasher().finish() | 1,
+            }),
+        }))
+    }
+
+    /// Whether `record` may be sent at `now`.
+    pub fn allow(&self, record: &Record, now: Instant) -> Verdict {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        if self.sample_rate < 1.0 && state.sample() >= self.sample_rate {
+            stats::record_suppressed();
+            return Verdict::Suppress;
+        }
+
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return Verdict::Allow,
+        };
+        let key = match limit.by {
+            LimitBy::Callsite => match (record.file(), record.line()) {
+                (Some(file), Some(line)) => format!("{}:{}", file, line),
+                _ => record.module_path().unwrap_or_else(|| record.target()).to_string(),
+            },
+            LimitBy::Module => record.module_path().unwrap_or_else(|| record.target()).to_string(),
+        };
+
+        let bucket = state.buckets.entry(key).or_insert_with(|| Bucket {
+            tokens: f64::from(limit.burst),
+            refilled: now,
+            suppressed: 0,
+            since: now,
+        });
+        let refill = now.saturating_duration_since(bucket.refilled).as_secs_f64()
+            / limit.per.as_secs_f64() * f64::from(limit.burst);
+        bucket.tokens = (bucket.tokens + refill).min(f64::from(limit.burst));
+        bucket.refilled = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Verdict::Allow;
+        }
+
+        stats::record_suppressed();
+        bucket.suppressed += 1;
+        if bucket.suppressed == 1 {
+            bucket.since = now;
+            // a window too long to represent never closes
+            if let Some(closes) = now.checked_add(limit.per) {
+                state.next_report = Some(state.next_report.map_or(closes, |next| next.min(closes)));
+            }
+            return Verdict::Opened;
+        }
+        Verdict::Suppress
+    }
+
+    /// When the earliest open suppression window closes.
+    pub fn next_report(&self) -> Option<Instant> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).next_report
+    }
+
+    /// Summaries of the suppression windows that closed by `now`.
+    pub fn closed(&self, now: Instant) -> Vec<String> {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return Vec::new(),
+        };
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.next_report.is_none_or(|next| now < next) {
+            return Vec::new();
+        }
+
+        let mut summaries = Vec::new();
+        let mut next_report = None;
+        for (key, bucket) in &mut state.buckets {
+            if bucket.suppressed == 0 {
+                continue;
+            }
+            let closes = match bucket.since.checked_add(limit.per) {
+                Some(closes) => closes,
+                None => continue,
+            };
+            if closes <= now {
+                summaries.push(format!(
+                    "suppressed {} record{} from {} in the last {:?}",
+                    bucket.suppressed,
+                    if bucket.suppressed == 1 { "" } else { "s" },
+                    key,
+                    now.duration_since(bucket.since),
+                ));
+                bucket.suppressed = 0;
+            } else {
+                next_report = Some(next_report.map_or(closes, |next: Instant| next.min(closes)));
+            }
+        }
+        state.next_report = next_report;
+        summaries.sort();
+        summaries
+    }
+}
+
+impl State {
+    /// A uniformly distributed number in `[0, 1)`, from xorshift64*.
+    fn sample(&mut self) -> f64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        let n = self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        (n >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use log::Level;
+
+    use config::DatadogConfig;
+    use datadog::DatadogSink;
+    use tcp::TcpLogsConfig;
+
+    fn allow(limiter: &Limiter, line: u32, now: Instant) -> bool {
+        limiter.allow(&Record::builder()
+            .module_path(Some("app"))
+            .file(Some("src/app.rs"))
+            .line(Some(line))
+            .build(), now) == Verdict::Allow
+    }
+
+    #[test]
+    fn limits_each_callsite_and_summarizes() {
+        let limiter = Limiter::new(Some(RateLimit::new(2, Duration::from_secs(10))), 1.0)
+            .unwrap()
+            .unwrap();
+        let start = Instant::now();
+
+        assert!(allow(&limiter, 1, start));
+        assert!(allow(&limiter, 1, start));
+        assert!(!allow(&limiter, 1, start));
+        assert!(!allow(&limiter, 1, start + Duration::from_secs(1)));
+        assert!(allow(&limiter, 2, start));
+        assert!(limiter.closed(start + Duration::from_secs(9)).is_empty());
+
+        // one token back every 5s
+        assert!(allow(&limiter, 1, start + Duration::from_secs(6)));
+        assert!(!allow(&limiter, 1, start + Duration::from_secs(6)));
+
+        assert_eq!(limiter.closed(start + Duration::from_secs(10)), vec![
+            "suppressed 3 records from src/app.rs:1 in the last 10s".to_string(),
+        ]);
+        assert!(limiter.closed(start + Duration::from_secs(30)).is_empty());
+    }
+
+    #[test]
+    fn windows_may_never_close() {
+        let limiter = Limiter::new(Some(RateLimit::new(1, Duration::MAX)), 1.0)
+            .unwrap()
+            .unwrap();
+        let start = Instant::now();
+
+        assert!(allow(&limiter, 1, start));
+        assert!(!allow(&limiter, 1, start + Duration::from_secs(3600)));
+        assert_eq!(limiter.next_report(), None);
+        assert!(limiter.closed(start + Duration::from_secs(7200)).is_empty());
+    }
+
+    #[test]
+    fn samples_and_validates() {
+        let limiter = Limiter::new(None, 0.25).unwrap().unwrap();
+        let now = Instant::now();
+        let kept = (0..10_000).filter(|_| allow(&limiter, 1, now)).count();
+        assert!(kept > 2_000 && kept < 3_000, "{}", kept);
+
+        assert!(Limiter::new(None, 1.0).unwrap().is_none());
+        assert!(Limiter::new(None, 1.5).is_err());
+        assert!(Limiter::new(Some(RateLimit::new(0, Duration::from_secs(1))), 1.0).is_err());
+    }
+
+    #[test]
+    fn summaries_go_out_without_further_logging() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = DatadogConfig::new()
+            .events(false)
+            .tcp_logs(TcpLogsConfig::new(listener.local_addr().unwrap().to_string()))
+            .rate_limit(RateLimit::new(1, Duration::from_millis(50)));
+        let sink = DatadogSink::new(config).unwrap();
+
+        for _ in 0..3 {
+            sink.log(&Record::builder()
+                .args(format_args!("disk full"))
+                .level(Level::Warn)
+                .file(Some("src/disk.rs"))
+                .line(Some(7))
+                .build(), None, Vec::new());
+        }
+        thread::sleep(Duration::from_millis(100));
+        sink.flush();
+
+        let (stream, _) = listener.accept().unwrap();
+        let lines: Vec<String> = BufReader::new(stream).lines().take(2).map(Result::unwrap).collect();
+        assert!(lines[0].contains("\"disk full\""), "{}", lines[0]);
+        assert!(lines[1].contains("suppressed 2 records from src/disk.rs:7"), "{}", lines[1]);
+    }
+}
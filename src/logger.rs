@@ -0,0 +1,374 @@
+use std::env;
+#[cfg(feature = "tracing")]
+use std::sync::Arc;
+
+use env_logger::filter::{self, Filter};
+use env_logger::Target;
+use log::{self, LevelFilter, Log, Metadata, Record};
+
+use console::Console;
+use datadog::DatadogSink;
+use error::Error;
+use format::{self, ColorChoice, Format, Layout, Precision, Theme, Timestamp};
+#[cfg(feature = "tracing")]
+use layer::FunkyLayer;
+use mdc;
+use trace::{LocalTrace, TraceProvider};
+use worker;
+
+/// A `log::Log` implementation that filters records like `env_logger` and
+/// hands every accepted record to the console and to Datadog.
+///
+/// The two outputs don't know about each other: changing the console
+/// layout doesn't touch what's sent to Datadog, and records reach Datadog
+/// whether or not they are printed nicely. Datadog can also have a filter of
+/// its own, see `Builder::datadog_filter`.
+pub struct FunkyLogger {
+    filter: Filter,
+    console: Console,
+    datadog: Option<(DatadogSink, Option<Filter>)>,
+    trace: Box<dyn TraceProvider>,
+}
+
+impl FunkyLogger {
+    #[cfg(test)]
+    pub(crate) fn sends_to_datadog(&self) -> bool {
+        self.datadog.is_some()
+    }
+
+    /// The most verbose level this logger lets through, to the console or
+    /// to Datadog.
+    pub fn filter(&self) -> LevelFilter {
+        match self.datadog {
+            Some((_, Some(ref dd_filter))) => self.filter.filter().max(dd_filter.filter()),
+            _ => self.filter.filter(),
+        }
+    }
+}
+
+impl Log for FunkyLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        if worker::on_worker() {
+            return false;
+        }
+        match self.datadog {
+            Some((_, Some(ref dd_filter))) => {
+                self.filter.enabled(metadata) || dd_filter.enabled(metadata)
+            }
+            _ => self.filter.enabled(metadata),
+        }
+    }
+
+    fn log(&self, record: &Record) {
+        // what the sender's HTTP client logs includes request headers, API
+        // key and all
+        if worker::on_worker() {
+            return;
+        }
+
+        let to_console = self.filter.matches(record);
+        let datadog = match self.datadog {
+            Some((ref datadog, Some(ref dd_filter))) if dd_filter.matches(record) => Some(datadog),
+            Some((ref datadog, None)) if to_console => Some(datadog),
+            _ => None,
+        };
+        if !to_console && datadog.is_none() {
+            return;
+        }
+
+        let trace = self.trace.current();
+        let mdc = mdc::current();
+        if to_console {
+            self.console.log(record, trace, &mdc);
+        }
+        if let Some(datadog) = datadog {
+            datadog.log(record, trace, mdc);
+        }
+    }
+
+    fn flush(&self) {
+        self.console.flush();
+        if let Some((ref datadog, _)) = self.datadog {
+            datadog.flush();
+        }
+    }
+}
+
+/// Configures and installs a `FunkyLogger`.
+///
+/// Returned by `formatted_builder` and `console_builder`. The filter methods
+/// mirror `env_logger::Builder`.
+pub struct Builder {
+    filter: filter::Builder,
+    target: Target,
+    format: Option<Format>,
+    layout: Layout,
+    color: ColorChoice,
+    datadog: Option<DatadogSink>,
+    dd_filter: Option<filter::Builder>,
+    trace: Option<Box<dyn TraceProvider>>,
+    built: bool,
+}
+
+impl Builder {
+    pub(crate) fn new(datadog: Option<DatadogSink>) -> Builder {
+        Builder {
+            filter: filter::Builder::new(),
+            target: Target::default(),
+            format: None,
+            layout: Layout::default(),
+            color: ColorChoice::Auto,
+            datadog,
+            dd_filter: None,
+            trace: None,
+            built: false,
+        }
+    }
+
+    /// Adds a directive to the filter for a specific module.
+    pub fn filter_module(&mut self, module: &str, level: LevelFilter) -> &mut Self {
+        self.filter.filter_module(module, level);
+        self
+    }
+
+    /// Adds a directive to the filter for all modules.
+    pub fn filter_level(&mut self, level: LevelFilter) -> &mut Self {
+        self.filter.filter_level(level);
+        self
+    }
+
+    /// Adds a directive to the filter, for all modules if `module` is `None`.
+    pub fn filter(&mut self, module: Option<&str>, level: LevelFilter) -> &mut Self {
+        self.filter.filter(module, level);
+        self
+    }
+
+    /// Parses directives in the same syntax as `RUST_LOG`.
+    pub fn parse(&mut self, filters: &str) -> &mut Self {
+        self.filter.parse(filters);
+        self
+    }
+
+    /// Parses directives in the `RUST_LOG` syntax for what is sent to
+    /// Datadog, e.g. `warn,myapp=info`. Calling it again adds directives.
+    ///
+    /// Defaults to `FUNKY_DD_LOG`. If neither is set, Datadog gets the same
+    /// records as the console.
+    pub fn datadog_filter(&mut self, filters: &str) -> &mut Self {
+        self.dd_filter.get_or_insert_with(filter::Builder::new).parse(filters);
+        self
+    }
+
+    /// Where console output goes. Defaults to stderr.
+    pub fn target(&mut self, target: Target) -> &mut Self {
+        self.target = target;
+        self
+    }
+
+    /// How console output is laid out. Defaults to `FUNKY_LOG_FORMAT`
+    /// (`pretty`, `json` or `logfmt`), or `Format::Pretty`.
+    pub fn format(&mut self, format: Format) -> &mut Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Which clock console timestamps show. Defaults to `Timestamp::Uptime`.
+    pub fn timestamp(&mut self, timestamp: Timestamp) -> &mut Self {
+        self.layout.timestamp = timestamp;
+        self
+    }
+
+    /// How many fractional digits console timestamps get. Defaults to
+    /// `Precision::Millis`.
+    pub fn timestamp_precision(&mut self, precision: Precision) -> &mut Self {
+        self.layout.precision = precision;
+        self
+    }
+
+    /// Whether console output is colored. Defaults to `ColorChoice::Auto`,
+    /// which honors `RUST_LOG_STYLE`, `NO_COLOR` and `CLICOLOR_FORCE` and
+    /// otherwise colors only when the target is a terminal.
+    pub fn color(&mut self, color: ColorChoice) -> &mut Self {
+        self.color = color;
+        self
+    }
+
+    /// Labels and colors of the pretty layout. Defaults to `Theme::funky`.
+    pub fn theme(&mut self, theme: Theme) -> &mut Self {
+        self.layout.theme = theme;
+        self
+    }
+
+    /// Whether the pretty header shows the `file:line` a record was logged
+    /// from. Off by default.
+    pub fn source_location(&mut self, enabled: bool) -> &mut Self {
+        self.layout.source_location = enabled;
+        self
+    }
+
+    /// Whether the pretty and logfmt layouts show the name of the thread
+    /// that logged, or its id if it has no name. Off by default; JSON output
+    /// always has it.
+    pub fn thread_name(&mut self, enabled: bool) -> &mut Self {
+        self.layout.thread_name = enabled;
+        self
+    }
+
+    /// Where the trace and span ids sent with each record come from.
+    /// Defaults to `LocalTrace`.
+    pub fn trace_provider<P: TraceProvider + 'static>(&mut self, provider: P) -> &mut Self {
+        self.trace = Some(Box::new(provider));
+        self
+    }
+
+    /// Builds the logger without installing it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called more than once.
+    pub fn build(&mut self) -> FunkyLogger {
+        assert!(!self.built, "attempt to re-use consumed builder");
+        self.built = true;
+        let is_terminal = format::is_terminal(&self.target);
+        self.layout.color = self.color.resolve(is_terminal, |name| env::var(name).ok());
+
+        FunkyLogger {
+            filter: self.filter.build(),
+            console: Console::new(
+                self.target,
+                self.format.unwrap_or_else(Format::from_env),
+                self.layout.clone(),
+            ),
+            datadog: self.datadog.take().map(|datadog| (datadog, self.build_dd_filter())),
+            trace: self.trace.take().unwrap_or_else(|| Box::new(LocalTrace)),
+        }
+    }
+
+    fn build_dd_filter(&mut self) -> Option<Filter> {
+        if self.dd_filter.is_none() {
+            if let Ok(filters) = env::var("FUNKY_DD_LOG") {
+                self.datadog_filter(&filters);
+            }
+        }
+        self.dd_filter.as_mut().map(filter::Builder::build)
+    }
+
+    /// Builds the logger as a `tracing_subscriber::Layer` instead of
+    /// installing it. Use `init_layer` to also send `log` records through
+    /// it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the builder was already used.
+    #[cfg(feature = "tracing")]
+    pub fn layer(&mut self) -> FunkyLayer {
+        FunkyLayer::new(self.build())
+    }
+
+    /// Builds the logger and installs it as the global logger.
+    pub fn try_init(&mut self) -> Result<(), Error> {
+        let logger = self.build();
+        let max_level = logger.filter();
+
+        log::set_boxed_logger(Box::new(logger))?;
+        log::set_max_level(max_level);
+        Ok(())
+    }
+
+    /// Like `try_init`, but panics if a global logger was already set.
+    pub fn init(&mut self) {
+        self.try_init().expect("Builder::init should not be called after logger initialized");
+    }
+
+    /// Installs the logger as the global logger and also returns it as a
+    /// `tracing_subscriber::Layer`, so records from `log` and events from
+    /// `tracing` share one set of filters and one Datadog sender.
+    #[cfg(feature = "tracing")]
+    pub fn try_init_layer(&mut self) -> Result<FunkyLayer, Error> {
+        let logger = Arc::new(self.build());
+        let max_level = logger.filter();
+
+        log::set_boxed_logger(Box::new(Shared(logger.clone())))?;
+        log::set_max_level(max_level);
+        Ok(FunkyLayer::shared(logger))
+    }
+
+    /// Like `try_init_layer`, but panics if a global logger was already set.
+    #[cfg(feature = "tracing")]
+    pub fn init_layer(&mut self) -> FunkyLayer {
+        self.try_init_layer().expect("Builder::init_layer should not be called after logger initialized")
+    }
+}
+
+/// The global logger when a layer holds the same `FunkyLogger`.
+#[cfg(feature = "tracing")]
+struct Shared(Arc<FunkyLogger>);
+
+#[cfg(feature = "tracing")]
+impl Log for Shared {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.0.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        self.0.log(record)
+    }
+
+    fn flush(&self) {
+        self.0.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    use log::Level;
+
+    use config::DatadogConfig;
+
+    #[test]
+    fn filters_like_env_logger() {
+        let logger = Builder::new(None).parse("warn,noisy::inner=trace").build();
+        let enabled = |level, target| {
+            logger.enabled(&Metadata::builder().level(level).target(target).build())
+        };
+
+        assert_eq!(logger.filter(), LevelFilter::Trace);
+        assert!(enabled(Level::Warn, "app"));
+        assert!(!enabled(Level::Info, "app"));
+        assert!(enabled(Level::Trace, "noisy::inner::deeper"));
+        assert!(!enabled(Level::Trace, "noisy"));
+    }
+
+    #[test]
+    fn filters_datadog_separately() {
+        let agent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        agent.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let config = DatadogConfig::new()
+            .host("127.0.0.1")
+            .port(agent.local_addr().unwrap().port());
+        let sink = DatadogSink::new(config).unwrap();
+        let logger = Builder::new(Some(sink))
+            .parse("warn")
+            .datadog_filter("info,noisy=off")
+            .build();
+        assert_eq!(logger.filter(), LevelFilter::Info);
+
+        for &(level, target) in &[(Level::Error, "noisy"), (Level::Debug, "app"), (Level::Info, "app")] {
+            logger.log(&Record::builder()
+                .args(format_args!("{} from {}", level, target))
+                .level(level)
+                .target(target)
+                .build());
+        }
+        logger.flush();
+
+        let mut buf = [0; 512];
+        let n = agent.recv(&mut buf).unwrap();
+        let datagram = String::from_utf8_lossy(&buf[..n]);
+        assert!(datagram.contains("|INFO from app|"), "{}", datagram);
+    }
+}
@@ -0,0 +1,134 @@
+use std::time::UNIX_EPOCH;
+
+use config::DatadogConfig;
+use datadog::DogLevel;
+use json;
+use kv;
+use record::OwnedRecord;
+use tags;
+
+// attributes written by `encode` that key-values must not overwrite
+const RESERVED: &[&str] = &[
+    "message", "status", "ddsource", "service", "hostname", "ddtags", "timestamp", "logger", "dd",
+];
+
+/// Encodes records as JSON log entries using Datadog's reserved attributes,
+/// for the outputs that ship logs rather than events.
+pub(crate) struct LogEncoder {
+    // fields that are the same for every entry
+    common: Vec<(&'static str, String)>,
+    tags: Vec<String>,
+    tag_keys: Vec<String>,
+    source_location: bool,
+}
+
+impl LogEncoder {
+    pub fn new(source: &str, config: &DatadogConfig, tags: &[String]) -> LogEncoder {
+        let mut common = vec![("ddsource", source.to_string())];
+        if let Some(ref service) = config.service {
+            common.push(("service", service.clone()));
+        }
+        if let Some(ref hostname) = config.hostname {
+            common.push(("hostname", hostname.clone()));
+        }
+        LogEncoder {
+            common,
+            tags: tags.to_vec(),
+            tag_keys: config.tag_keys.clone(),
+            source_location: config.source_location,
+        }
+    }
+
+    /// Key-values whose key is in `DatadogConfig::tag_key` go to `ddtags`,
+    /// the rest become top-level attributes.
+    pub fn encode(&self, record: &OwnedRecord) -> String {
+        let mut out = String::with_capacity(record.message.len() + 256);
+        let mut obj = json::Object::new(&mut out);
+        obj.string("message", &record.message)
+            .string("status", &DogLevel(record.level).to_string());
+        for &(key, ref value) in &self.common {
+            obj.string(key, value);
+        }
+
+        let mut tags = self.tags.clone();
+        tags.extend(tags::promoted(&record.fields, &self.tag_keys));
+        if !tags.is_empty() {
+            obj.string("ddtags", &tags.join(","));
+        }
+
+        if let Ok(since_epoch) = record.time.duration_since(UNIX_EPOCH) {
+            obj.number("timestamp", since_epoch.as_millis() as u64);
+        }
+        {
+            let mut logger = obj.object("logger");
+            logger.string("name", &record.target);
+            if self.source_location {
+                if let Some(ref file) = record.file {
+                    logger.string("file", file);
+                }
+                if let Some(line) = record.line {
+                    logger.number("line", u64::from(line));
+                }
+            }
+            logger.string("thread_name", &record.thread).finish();
+        }
+        if let Some(trace) = record.trace {
+            trace.write_json(&mut obj);
+        }
+        for (key, value) in &record.fields {
+            if !self.tag_keys.contains(key) {
+                value.write_json(&mut obj, &kv::json_key(key, RESERVED));
+            }
+        }
+        obj.finish();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use log::Level;
+
+    use kv::Value;
+    use trace::TraceContext;
+
+    #[test]
+    fn splits_key_values_into_tags_and_attributes() {
+        let config = DatadogConfig::new().tag_key("tenant");
+        let encoder = LogEncoder::new("rust", &config, &["env:test".into()]);
+        let record = OwnedRecord {
+            file: Some("src/pay.rs".into()),
+            line: Some(31),
+            trace: Some(TraceContext::new(7, 8)),
+            fields: vec![
+                ("tenant".into(), Value::Str("Acme Corp".into())),
+                ("amount".into(), Value::Uint(12)),
+                ("status".into(), Value::Str("settled".into())),
+            ],
+            ..OwnedRecord::test(Level::Info, "app", "paid")
+        };
+
+        assert_eq!(encoder.encode(&record), concat!(
+            r#"{"message":"paid","status":"info","ddsource":"rust","ddtags":"env:test,tenant:acme_corp","#,
+            r#""timestamp":1700000000123,"logger":{"name":"app","thread_name":"main"},"#,
+            r#""dd":{"trace_id":"7","span_id":"8"},"amount":12,"kv.status":"settled"}"#,
+        ));
+    }
+
+    #[test]
+    fn sends_source_location_when_enabled() {
+        let config = DatadogConfig::new().source_location(true);
+        let encoder = LogEncoder::new("rust", &config, &[]);
+        let record = OwnedRecord {
+            file: Some("src/pay.rs".into()),
+            line: Some(31),
+            ..OwnedRecord::test(Level::Info, "app", "paid")
+        };
+
+        assert!(encoder.encode(&record).contains(
+            r#""logger":{"name":"app","file":"src/pay.rs","line":31,"thread_name":"main"}"#
+        ));
+    }
+}
@@ -0,0 +1,148 @@
+use std::cell::RefCell;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{self, Poll};
+
+use log::kv::ToValue;
+
+use kv::{self, Fields, Value};
+
+thread_local! {
+    // every entered field, outermost first
+    static STACK: RefCell<Fields> = const { RefCell::new(Vec::new()) };
+}
+
+/// Fields added to every record logged while they're entered, like
+/// request ids in a request handler.
+///
+/// They're rendered like the record's own key-values and reach Datadog the
+/// same way, as attributes or as tags for keys given to
+/// `DatadogConfig::tag_key`. When keys repeat, the innermost context wins,
+/// and the record's own key-values win over all of them.
+///
+/// ```
+/// # extern crate funky_logger;
+/// # #[macro_use] extern crate log;
+/// use funky_logger::Mdc;
+///
+/// # fn main() {
+/// let _mdc = Mdc::new().field("request_id", "8f2a").field("user_id", 42).enter();
+/// info!("logged with request_id=8f2a user_id=42");
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Mdc {
+    fields: Fields,
+}
+
+impl Mdc {
+    pub fn new() -> Mdc {
+        Mdc::default()
+    }
+
+    /// Adds a field. Numbers and booleans keep their type in JSON.
+    pub fn field<K: Into<String>, V: ToValue>(mut self, key: K, value: V) -> Mdc {
+        kv::set(&mut self.fields, key.into(), Value::new(&value.to_value()));
+        self
+    }
+
+    /// Adds the fields to records logged on this thread until the guard is
+    /// dropped. Guards must be dropped in reverse order of entering.
+    pub fn enter(self) -> MdcGuard {
+        MdcGuard {
+            len: STACK.with(|stack| {
+                let mut stack = stack.borrow_mut();
+                let len = stack.len();
+                stack.extend(self.fields);
+                len
+            }),
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Adds the fields to records logged while `future` is polled, wherever
+    /// the executor runs it.
+    pub fn scope<F: Future>(self, future: F) -> InMdc<F> {
+        InMdc {
+            mdc: self,
+            future: Box::pin(future),
+        }
+    }
+}
+
+/// Removes the fields of an entered `Mdc` when dropped.
+#[must_use = "the fields are only added while the guard is alive"]
+pub struct MdcGuard {
+    len: usize,
+    // the fields belong to the thread the guard was made on
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for MdcGuard {
+    fn drop(&mut self) {
+        STACK.with(|stack| stack.borrow_mut().truncate(self.len));
+    }
+}
+
+/// A future running with an `Mdc`, made by `Mdc::scope`.
+pub struct InMdc<F> {
+    mdc: Mdc,
+    future: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for InMdc<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context) -> Poll<F::Output> {
+        let _mdc = self.mdc.clone().enter();
+        self.future.as_mut().poll(cx)
+    }
+}
+
+/// The fields entered on this thread, with repeated keys resolved.
+pub(crate) fn current() -> Fields {
+    STACK.with(|stack| {
+        let mut fields = Fields::new();
+        for (key, value) in stack.borrow().iter() {
+            kv::set(&mut fields, key.clone(), value.clone());
+        }
+        fields
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future;
+    use std::task::Waker;
+
+    fn field(key: &str, value: Value) -> (String, Value) {
+        (key.to_string(), value)
+    }
+
+    #[test]
+    fn inner_contexts_override_and_unwind() {
+        let _outer = Mdc::new().field("request_id", "a").field("user_id", 7).enter();
+        {
+            let _inner = Mdc::new().field("request_id", "b").enter();
+            assert_eq!(current(), vec![
+                field("request_id", Value::Str("b".into())),
+                field("user_id", Value::Uint(7)),
+            ]);
+        }
+        assert_eq!(current(), vec![
+            field("request_id", Value::Str("a".into())),
+            field("user_id", Value::Uint(7)),
+        ]);
+
+        let mut future = Mdc::new().field("job", true).scope(future::poll_fn(|_| Poll::Ready(current())));
+        let mut cx = task::Context::from_waker(Waker::noop());
+        assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Ready(vec![
+            field("request_id", Value::Str("a".into())),
+            field("user_id", Value::Uint(7)),
+            field("job", Value::Bool(true)),
+        ]));
+        assert_eq!(current().len(), 2);
+    }
+}
@@ -0,0 +1,72 @@
+use std::time::{Duration, SystemTime};
+
+use log::{Level, Record};
+
+use format;
+use kv::{self, Fields};
+use trace::TraceContext;
+
+/// An owned copy of a `log::Record`, for handing to the sender thread.
+pub(crate) struct OwnedRecord {
+    pub level: Level,
+    pub target: String,
+    pub module_path: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub thread: String,
+    pub trace: Option<TraceContext>,
+    pub message: String,
+    pub fields: Fields,
+    pub time: SystemTime,
+}
+
+impl OwnedRecord {
+    pub fn new(record: &Record, trace: Option<TraceContext>, mdc: Fields) -> OwnedRecord {
+        OwnedRecord {
+            level: record.level(),
+            target: record.target().to_string(),
+            module_path: record.module_path().map(str::to_string),
+            file: record.file().map(str::to_string),
+            line: record.line(),
+            thread: format::thread_name(),
+            trace,
+            message: record.args().to_string(),
+            fields: kv::merge(mdc, record),
+            time: SystemTime::now(),
+        }
+    }
+
+    /// A record from `target` on the `main` thread at a fixed time, for
+    /// tests to adjust as needed.
+    #[cfg(test)]
+    pub fn test(level: Level, target: &str, message: &str) -> OwnedRecord {
+        OwnedRecord {
+            level,
+            target: target.into(),
+            module_path: Some(target.into()),
+            file: None,
+            line: None,
+            thread: "main".into(),
+            trace: None,
+            message: message.into(),
+            fields: Fields::new(),
+            time: SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+        }
+    }
+}
+
+/// One of the outputs on the sender thread.
+pub(crate) trait Sink: Send {
+    fn send(&mut self, record: &OwnedRecord);
+
+    /// Called when the queue runs empty and when the delay it last returned
+    /// has passed. Sinks that batch or retry do what is due here.
+    fn idle(&mut self) -> Option<Duration> {
+        None
+    }
+
+    /// Sends everything held back. Returns whether it was all delivered.
+    fn flush(&mut self) -> bool {
+        true
+    }
+}
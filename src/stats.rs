@@ -0,0 +1,160 @@
+use std::cell::Cell;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+static SENT: AtomicU64 = AtomicU64::new(0);
+static SEND_ERRORS: AtomicU64 = AtomicU64::new(0);
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+static SUPPRESSED: AtomicU64 = AtomicU64::new(0);
+
+static POLICY: RwLock<ErrorPolicy> = RwLock::new(ErrorPolicy::ReportOnce);
+static REPORTED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static IN_HOOK: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Counters describing what happened to records sent to Datadog.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Records delivered to Datadog, counted once per output.
+    pub sent: u64,
+    /// Records that failed to send, counted once per output.
+    pub send_errors: u64,
+    /// Records dropped because the queue was full or shut down, or because
+    /// a disconnected output ran out of buffer space.
+    pub dropped: u64,
+    /// Records not sent because of the rate limit or sampling.
+    pub suppressed: u64,
+}
+
+/// Returns the counters accumulated since the process started.
+pub fn stats() -> Stats {
+    Stats {
+        sent: SENT.load(Ordering::Relaxed),
+        send_errors: SEND_ERRORS.load(Ordering::Relaxed),
+        dropped: DROPPED.load(Ordering::Relaxed),
+        suppressed: SUPPRESSED.load(Ordering::Relaxed),
+    }
+}
+
+/// What to do when sending to Datadog fails.
+///
+/// Failures are always counted in `stats()`, whatever the policy.
+#[derive(Clone, Default)]
+pub enum ErrorPolicy {
+    /// Drop the records silently.
+    Ignore,
+    /// Print the first failure to stderr and stay quiet afterwards.
+    #[default]
+    ReportOnce,
+    /// Call the hook for every failure.
+    ///
+    /// Records logged from inside the hook are not sent to Datadog, so a
+    /// hook that logs can't feed back into itself.
+    Hook(Arc<dyn Fn(&io::Error) + Send + Sync>),
+}
+
+impl ErrorPolicy {
+    /// Shorthand for `ErrorPolicy::Hook(Arc::new(f))`.
+    pub fn hook<F>(f: F) -> ErrorPolicy
+        where F: Fn(&io::Error) + Send + Sync + 'static
+    {
+        ErrorPolicy::Hook(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for ErrorPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorPolicy::Ignore => f.write_str("Ignore"),
+            ErrorPolicy::ReportOnce => f.write_str("ReportOnce"),
+            ErrorPolicy::Hook(_) => f.write_str("Hook(..)"),
+        }
+    }
+}
+
+/// Sets how failed Datadog sends are reported. Defaults to `ReportOnce`.
+pub fn set_error_policy(policy: ErrorPolicy) {
+    *POLICY.write().unwrap_or_else(|e| e.into_inner()) = policy;
+}
+
+pub(crate) fn in_hook() -> bool {
+    IN_HOOK.with(|h| h.get())
+}
+
+pub(crate) fn record_sent(records: u64) {
+    SENT.fetch_add(records, Ordering::Relaxed);
+}
+
+pub(crate) fn record_dropped() {
+    DROPPED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn record_suppressed() {
+    SUPPRESSED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Clears `IN_HOOK` even when the hook panics.
+struct HookGuard;
+
+impl Drop for HookGuard {
+    fn drop(&mut self) {
+        IN_HOOK.with(|h| h.set(false));
+    }
+}
+
+/// Counts `records` lost to `e` and reports it according to the policy.
+pub(crate) fn record_send_error(e: &io::Error, records: u64) {
+    SEND_ERRORS.fetch_add(records, Ordering::Relaxed);
+
+    let policy = POLICY.read().unwrap_or_else(|e| e.into_inner()).clone();
+    match policy {
+        ErrorPolicy::Ignore => {}
+        ErrorPolicy::ReportOnce => {
+            if !REPORTED.swap(true, Ordering::Relaxed) {
+                eprintln!("funky_logger: failed to send to datadog: {} \
+                           (further failures will not be reported)", e);
+            }
+        }
+        ErrorPolicy::Hook(hook) => {
+            IN_HOOK.with(|h| h.set(true));
+            let _reset = HookGuard;
+            hook(e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn hook_sees_errors_and_counts_them() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let hook_calls = calls.clone();
+        set_error_policy(ErrorPolicy::hook(move |e| {
+            assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+            assert!(in_hook());
+            hook_calls.fetch_add(1, Ordering::SeqCst);
+        }));
+
+        let before = stats().send_errors;
+        record_send_error(&io::Error::from(io::ErrorKind::WouldBlock), 1);
+        record_send_error(&io::Error::from(io::ErrorKind::WouldBlock), 1);
+
+        // a panicking hook still leaves the flag cleared
+        set_error_policy(ErrorPolicy::hook(|_| panic!("hook failed")));
+        let result = panic::catch_unwind(|| record_send_error(&io::Error::from(io::ErrorKind::WouldBlock), 1));
+        set_error_policy(ErrorPolicy::default());
+
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert!(stats().send_errors >= before + 3);
+        assert!(!in_hook());
+    }
+}
@@ -0,0 +1,99 @@
+use error::Error;
+use kv::Fields;
+
+/// Longest tag Datadog accepts, in characters.
+const MAX_TAG_LEN: usize = 200;
+
+/// Normalizes a tag the way the Datadog agent would: lowercased, starting
+/// with a letter, with characters outside `[a-z0-9_\-:./]` (letters of any
+/// script are fine) turned into single underscores, and cut at 200
+/// characters.
+pub(crate) fn normalize(tag: &str) -> String {
+    let mut out = String::with_capacity(tag.len());
+    let mut len = 0;
+
+    for c in tag.chars() {
+        if len == MAX_TAG_LEN {
+            break;
+        }
+        if len == 0 && !c.is_alphabetic() {
+            continue;
+        }
+
+        if c.is_alphanumeric() || c == '_' || c == '-' || c == ':' || c == '.' || c == '/' {
+            for lower in c.to_lowercase() {
+                if len < MAX_TAG_LEN {
+                    out.push(lower);
+                    len += 1;
+                }
+            }
+        } else if !out.ends_with('_') {
+            out.push('_');
+            len += 1;
+        }
+    }
+
+    while out.ends_with('_') {
+        out.pop();
+    }
+    out
+}
+
+/// Builds a normalized `key:value` tag, rejecting keys that normalize to
+/// nothing.
+pub(crate) fn key_value(key: &str, value: &str) -> Result<String, Error> {
+    if normalize(key).is_empty() {
+        return Err(Error::Config(format!("invalid tag key: {:?}", key)));
+    }
+    Ok(normalize(&format!("{}:{}", key, value)))
+}
+
+/// Tags for the key-values whose key is in `keys`. Values that don't make a
+/// valid tag are left out.
+pub(crate) fn promoted(fields: &Fields, keys: &[String]) -> Vec<String> {
+    fields.iter()
+        .filter(|(key, _)| keys.contains(key))
+        .filter_map(|(key, value)| key_value(key, &value.to_string()).ok())
+        .collect()
+}
+
+/// Normalizes a tag given as a single string, rejecting ones that normalize
+/// to nothing.
+pub(crate) fn parse(tag: &str) -> Result<String, Error> {
+    let normalized = normalize(tag);
+    if normalized.is_empty() || normalized.starts_with(':') {
+        return Err(Error::Config(format!("invalid tag: {:?}", tag)));
+    }
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_like_the_agent() {
+        assert_eq!(normalize("Region:EU-West-1"), "region:eu-west-1");
+        assert_eq!(normalize("module:funky_logger::tests"), "module:funky_logger::tests");
+        assert_eq!(normalize("path:/var/log/app.log"), "path:/var/log/app.log");
+        assert_eq!(normalize("user name:J. Doe!!"), "user_name:j._doe");
+        assert_eq!(normalize("#1 pod"), "pod");
+        assert_eq!(normalize("städte:köln"), "städte:köln");
+        assert_eq!(normalize("123"), "");
+    }
+
+    #[test]
+    fn truncates_to_200_characters() {
+        let long = format!("key:{}", "ä".repeat(300));
+        let normalized = normalize(&long);
+        assert_eq!(normalized.chars().count(), 200);
+        assert!(normalized.starts_with("key:ää"));
+    }
+
+    #[test]
+    fn rejects_empty_keys() {
+        assert_eq!(key_value("Pod", "xyz-1").unwrap(), "pod:xyz-1");
+        assert!(key_value("!!", "value").is_err());
+        assert!(parse("42").is_err());
+    }
+}
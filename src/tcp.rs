@@ -0,0 +1,325 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use config::DatadogConfig;
+use logs::LogEncoder;
+use record::{OwnedRecord, Sink};
+use stats;
+
+/// Settings for forwarding logs to a TCP port the Datadog agent listens on.
+///
+/// Records are written as newline-delimited JSON with the same fields as
+/// `HttpLogsConfig` sends. While the connection is down, lines are kept in
+/// a bounded buffer and the sink reconnects with exponential backoff.
+///
+/// Delivery is at least once. A line whose write fails partway, e.g. on a
+/// write timeout, is sent again in full on the next connection, so the agent
+/// may log the part that got through as well as the whole line.
+///
+/// The agent needs a matching `logs` entry in its configuration:
+///
+/// ```yaml
+/// logs:
+///   - type: tcp
+///     port: 10518
+///     service: billing
+///     source: rust
+/// ```
+///
+/// ```no_run
+/// use funky_logger::{DatadogConfig, TcpLogsConfig};
+///
+/// let config = DatadogConfig::new()
+///     .events(false)
+///     .tcp_logs(TcpLogsConfig::new("127.0.0.1:10518"));
+/// funky_logger::formatted_builder_with(config).unwrap().init();
+/// ```
+#[derive(Clone, Debug)]
+pub struct TcpLogsConfig {
+    addr: String,
+    source: String,
+    max_buffered_lines: usize,
+    min_backoff: Duration,
+    max_backoff: Duration,
+    timeout: Duration,
+}
+
+impl TcpLogsConfig {
+    /// Forwards to the agent listening on `addr`, e.g. `127.0.0.1:10518`.
+    pub fn new<S: Into<String>>(addr: S) -> Self {
+        TcpLogsConfig {
+            addr: addr.into(),
+            source: "rust".to_string(),
+            max_buffered_lines: 1000,
+            min_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    /// The `ddsource` of every line. Defaults to `rust`.
+    pub fn source<S: Into<String>>(mut self, source: S) -> Self {
+        self.source = source.into();
+        self
+    }
+
+    /// How many lines to keep while disconnected. Defaults to 1000; when
+    /// full the oldest line is dropped.
+    pub fn max_buffered_lines(mut self, lines: usize) -> Self {
+        self.max_buffered_lines = lines.max(1);
+        self
+    }
+
+    /// The first and the longest wait between reconnection attempts.
+    /// Defaults to 100 milliseconds and 30 seconds.
+    pub fn backoff(mut self, min: Duration, max: Duration) -> Self {
+        self.min_backoff = min;
+        self.max_backoff = max.max(min);
+        self
+    }
+
+    /// How long connecting or writing may take. Defaults to 1 second.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// Writes records as JSON lines to the agent, reconnecting when needed.
+pub(crate) struct TcpSink {
+    addr: String,
+    encoder: LogEncoder,
+    stream: Option<TcpStream>,
+    buffer: VecDeque<String>,
+    max_buffered_lines: usize,
+    min_backoff: Duration,
+    max_backoff: Duration,
+    backoff: Duration,
+    // `None` once the backoff is too long to represent: no more attempts
+    next_attempt: Option<Instant>,
+    timeout: Duration,
+}
+
+impl TcpSink {
+    pub fn new(logs: &TcpLogsConfig, config: &DatadogConfig, tags: &[String]) -> TcpSink {
+        TcpSink {
+            addr: logs.addr.clone(),
+            encoder: LogEncoder::new(&logs.source, config, tags),
+            stream: None,
+            buffer: VecDeque::new(),
+            max_buffered_lines: logs.max_buffered_lines,
+            min_backoff: logs.min_backoff,
+            max_backoff: logs.max_backoff,
+            backoff: logs.min_backoff,
+            next_attempt: Some(Instant::now()),
+            timeout: logs.timeout,
+        }
+    }
+
+    fn connect(&mut self) -> io::Result<TcpStream> {
+        let mut last_err = io::Error::new(io::ErrorKind::NotFound, "address resolved to nothing");
+        for addr in self.addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, self.timeout) {
+                Ok(stream) => {
+                    stream.set_write_timeout(Some(self.timeout))?;
+                    stream.set_nodelay(true)?;
+                    return Ok(stream);
+                }
+                Err(e) => last_err = e,
+            }
+        }
+        Err(last_err)
+    }
+
+    /// Writes out as much of the buffer as the connection allows.
+    fn drain(&mut self) {
+        if self.stream.is_none() {
+            if self.next_attempt.is_none_or(|next| Instant::now() < next) {
+                return;
+            }
+            match self.connect() {
+                Ok(stream) => {
+                    self.stream = Some(stream);
+                    self.backoff = self.min_backoff;
+                }
+                Err(e) => {
+                    self.disconnected(&e);
+                    return;
+                }
+            }
+        }
+
+        while let Some(line) = self.buffer.front() {
+            let result = match self.stream {
+                Some(ref mut stream) => stream.write_all(line.as_bytes()),
+                None => return,
+            };
+            match result {
+                Ok(()) => {
+                    self.buffer.pop_front();
+                    stats::record_sent(1);
+                }
+                Err(e) => {
+                    // the line stays buffered and goes out whole on the next
+                    // connection; a new connection can't continue a line the
+                    // agent already ended with the old one
+                    self.stream = None;
+                    self.disconnected(&e);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn disconnected(&mut self, e: &io::Error) {
+        // nothing is lost yet, so report without counting records
+        stats::record_send_error(e, 0);
+        self.next_attempt = Instant::now().checked_add(self.backoff);
+        self.backoff = self.backoff.checked_mul(2).map_or(self.max_backoff, |b| b.min(self.max_backoff));
+    }
+}
+
+impl Sink for TcpSink {
+    fn send(&mut self, record: &OwnedRecord) {
+        let mut line = self.encoder.encode(record);
+        line.push('\n');
+
+        if self.buffer.len() >= self.max_buffered_lines {
+            self.buffer.pop_front();
+            stats::record_dropped();
+        }
+        self.buffer.push_back(line);
+        self.drain();
+    }
+
+    fn idle(&mut self) -> Option<Duration> {
+        self.drain();
+        // buffered lines are retried once the backoff is over
+        if self.buffer.is_empty() {
+            None
+        } else {
+            self.next_attempt.map(|next| next.saturating_duration_since(Instant::now()))
+        }
+    }
+
+    fn flush(&mut self) -> bool {
+        self.drain();
+        self.buffer.is_empty()
+    }
+}
+
+impl Drop for TcpSink {
+    fn drop(&mut self) {
+        for _ in self.buffer.drain(..) {
+            stats::record_dropped();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use log::Level;
+
+    use datadog::Dispatch;
+    use worker::{Overflow, Worker};
+
+    fn record(message: &str) -> OwnedRecord {
+        OwnedRecord::test(Level::Warn, "app", message)
+    }
+
+    fn sink(addr: &str, max_buffered_lines: usize) -> TcpSink {
+        let logs = TcpLogsConfig::new(addr)
+            .max_buffered_lines(max_buffered_lines)
+            .backoff(Duration::from_millis(1), Duration::from_millis(1));
+        TcpSink::new(&logs, &DatadogConfig::new().service("billing"), &[])
+    }
+
+    fn read_lines(listener: &TcpListener, n: usize) -> Vec<String> {
+        let (stream, _) = listener.accept().unwrap();
+        BufReader::new(stream).lines().take(n).map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn writes_json_lines() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut sink = sink(&listener.local_addr().unwrap().to_string(), 10);
+
+        sink.send(&record("first"));
+        sink.send(&record("second\nline"));
+
+        assert_eq!(read_lines(&listener, 2), vec![
+            r#"{"message":"first","status":"warning","ddsource":"rust","service":"billing","timestamp":1700000000123,"logger":{"name":"app","thread_name":"main"}}"#,
+            r#"{"message":"second\nline","status":"warning","ddsource":"rust","service":"billing","timestamp":1700000000123,"logger":{"name":"app","thread_name":"main"}}"#,
+        ]);
+    }
+
+    #[test]
+    fn buffers_while_disconnected_and_reconnects() {
+        // find a free port, then leave it closed for a while
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let mut sink = sink(&addr.to_string(), 2);
+
+        let dropped = stats::stats().dropped;
+        sink.send(&record("lost"));
+        sink.send(&record("kept 1"));
+        sink.send(&record("kept 2"));
+        assert!(sink.stream.is_none());
+        assert_eq!(sink.buffer.len(), 2);
+        assert!(stats::stats().dropped > dropped);
+
+        let listener = TcpListener::bind(addr).unwrap();
+        thread::sleep(Duration::from_millis(5));
+        sink.flush();
+
+        let lines = read_lines(&listener, 2);
+        assert!(lines[0].contains("\"kept 1\""));
+        assert!(lines[1].contains("\"kept 2\""));
+        assert!(sink.buffer.is_empty());
+    }
+
+    #[test]
+    fn retries_buffered_lines_without_new_records() {
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let worker = Worker::spawn(16, Overflow::Block, Duration::from_millis(100),
+            Dispatch::new(vec![Box::new(sink(&addr.to_string(), 10))], None)).unwrap();
+
+        worker.push(record("late"));
+        assert!(!worker.flush());
+
+        // nothing else is logged once the agent comes up
+        let listener = TcpListener::bind(addr).unwrap();
+        assert!(read_lines(&listener, 1)[0].contains("\"late\""));
+        assert!(worker.flush());
+    }
+
+    #[test]
+    fn counts_lines_left_at_shutdown_as_dropped() {
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let worker = Worker::spawn(16, Overflow::Block, Duration::from_secs(5),
+            Dispatch::new(vec![Box::new(sink(&addr.to_string(), 10))], None)).unwrap();
+
+        let dropped = stats::stats().dropped;
+        worker.push(record("never sent"));
+        worker.shutdown();
+        assert!(stats::stats().dropped > dropped);
+    }
+
+    #[test]
+    fn backoff_may_be_unbounded() {
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let logs = TcpLogsConfig::new(addr.to_string()).backoff(Duration::MAX, Duration::MAX);
+        let mut sink = TcpSink::new(&logs, &DatadogConfig::new(), &[]);
+
+        sink.send(&record("never sent"));
+        sink.send(&record("never sent either"));
+        assert_eq!(sink.idle(), None);
+        assert!(!sink.flush());
+    }
+}
@@ -0,0 +1,189 @@
+use std::cell::Cell;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{self, Poll};
+
+use json::Object;
+
+/// The APM trace a record was logged in, for correlating logs with traces.
+///
+/// Sent as `dd.trace_id` and `dd.span_id`. Datadog correlates on the lower
+/// 64 bits of the trace id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TraceContext {
+    pub trace_id: u64,
+    pub span_id: u64,
+}
+
+impl TraceContext {
+    pub fn new(trace_id: u64, span_id: u64) -> TraceContext {
+        TraceContext { trace_id, span_id }
+    }
+
+    /// Writes the `dd` object with the ids as decimal strings, the way
+    /// Datadog's tracers inject them.
+    pub(crate) fn write_json(&self, obj: &mut Object) {
+        obj.object("dd")
+            .string("trace_id", &self.trace_id.to_string())
+            .string("span_id", &self.span_id.to_string())
+            .finish();
+    }
+
+    /// `dd.trace_id:` and `dd.span_id:` tags for events.
+    pub(crate) fn tags(&self) -> [String; 2] {
+        [format!("dd.trace_id:{}", self.trace_id), format!("dd.span_id:{}", self.span_id)]
+    }
+}
+
+/// Tells the logger which trace is active when a record is logged.
+///
+/// Queried once per record, on the thread that logs it. Closures returning
+/// `Option<TraceContext>` implement it too.
+pub trait TraceProvider: Send + Sync {
+    fn current(&self) -> Option<TraceContext>;
+}
+
+impl<F> TraceProvider for F
+where
+    F: Fn() -> Option<TraceContext> + Send + Sync,
+{
+    fn current(&self) -> Option<TraceContext> {
+        self()
+    }
+}
+
+thread_local! {
+    static CURRENT: Cell<Option<TraceContext>> = const { Cell::new(None) };
+}
+
+/// The default `TraceProvider`: a trace set per thread with `enter`, or per
+/// task with `scope`.
+///
+/// ```
+/// # extern crate funky_logger;
+/// # #[macro_use] extern crate log;
+/// use funky_logger::{LocalTrace, TraceContext};
+///
+/// # fn main() {
+/// let _trace = LocalTrace::enter(TraceContext::new(1234, 5678));
+/// info!("sent with dd.trace_id 1234 and dd.span_id 5678");
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LocalTrace;
+
+impl LocalTrace {
+    /// Makes `trace` current on this thread until the guard is dropped,
+    /// when the previous one is restored.
+    pub fn enter(trace: TraceContext) -> TraceGuard {
+        TraceGuard {
+            previous: CURRENT.with(|current| current.replace(Some(trace))),
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Makes `trace` current whenever `future` is polled, wherever the
+    /// executor runs it.
+    pub fn scope<F: Future>(trace: TraceContext, future: F) -> InTrace<F> {
+        InTrace {
+            trace,
+            future: Box::pin(future),
+        }
+    }
+}
+
+impl TraceProvider for LocalTrace {
+    fn current(&self) -> Option<TraceContext> {
+        CURRENT.with(Cell::get)
+    }
+}
+
+/// Restores the previous trace of the thread when dropped.
+#[must_use = "the trace is only current while the guard is alive"]
+pub struct TraceGuard {
+    previous: Option<TraceContext>,
+    // the trace belongs to the thread the guard was made on
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for TraceGuard {
+    fn drop(&mut self) {
+        CURRENT.with(|current| current.set(self.previous));
+    }
+}
+
+/// A future running in a trace, made by `LocalTrace::scope`.
+pub struct InTrace<F> {
+    trace: TraceContext,
+    future: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for InTrace<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context) -> Poll<F::Output> {
+        let _trace = LocalTrace::enter(self.trace);
+        self.future.as_mut().poll(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future;
+    use std::net::UdpSocket;
+    use std::task::Waker;
+    use std::time::Duration;
+
+    use log::{Level, Log, Record};
+
+    use config::DatadogConfig;
+    use datadog::DatadogSink;
+    use logger::Builder;
+
+    #[test]
+    fn guards_nest_and_futures_carry_their_trace() {
+        let outer = TraceContext::new(1, 2);
+        let inner = TraceContext::new(1, 3);
+
+        assert_eq!(LocalTrace.current(), None);
+        {
+            let _outer = LocalTrace::enter(outer);
+            {
+                let _inner = LocalTrace::enter(inner);
+                assert_eq!(LocalTrace.current(), Some(inner));
+            }
+            assert_eq!(LocalTrace.current(), Some(outer));
+        }
+        assert_eq!(LocalTrace.current(), None);
+
+        let mut future = LocalTrace::scope(inner, future::poll_fn(|_| Poll::Ready(LocalTrace.current())));
+        let mut cx = task::Context::from_waker(Waker::noop());
+        assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Ready(Some(inner)));
+        assert_eq!(LocalTrace.current(), None);
+    }
+
+    #[test]
+    fn events_carry_the_current_trace() {
+        let agent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        agent.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let config = DatadogConfig::new()
+            .host("127.0.0.1")
+            .port(agent.local_addr().unwrap().port());
+        let logger = Builder::new(Some(DatadogSink::new(config).unwrap())).parse("info").build();
+
+        let _trace = LocalTrace::enter(TraceContext::new(11, 12));
+        logger.log(&Record::builder()
+            .args(format_args!("charged"))
+            .level(Level::Info)
+            .module_path(Some("app"))
+            .build());
+        logger.flush();
+
+        let mut buf = [0; 512];
+        let n = agent.recv(&mut buf).unwrap();
+        let datagram = String::from_utf8_lossy(&buf[..n]);
+        assert!(datagram.ends_with("|#level:info,module:app,dd.trace_id:11,dd.span_id:12"), "{}", datagram);
+    }
+}
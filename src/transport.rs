@@ -0,0 +1,132 @@
+use std::io;
+use std::net::UdpSocket;
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Where DogStatsD datagrams are sent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Endpoint {
+    /// UDP to `agent`, sent from a socket bound to `local`.
+    Udp { local: String, agent: String },
+    /// A Unix domain datagram socket, like `/var/run/datadog/dsd.socket`.
+    Unix(PathBuf),
+}
+
+/// Sends encoded datagrams to the agent.
+pub(crate) trait Transport: Send {
+    fn send(&mut self, datagram: &[u8]) -> io::Result<()>;
+}
+
+/// Opens a transport for `endpoint`. Writes give up after `write_timeout`.
+pub(crate) fn open(endpoint: &Endpoint, write_timeout: Option<Duration>)
+    -> io::Result<Box<dyn Transport>>
+{
+    match *endpoint {
+        Endpoint::Udp { ref local, ref agent } => {
+            let socket = UdpSocket::bind(local.as_str())?;
+            socket.set_write_timeout(write_timeout)?;
+            Ok(Box::new(Udp { socket, agent: agent.clone() }))
+        }
+        #[cfg(unix)]
+        Endpoint::Unix(ref path) => {
+            let socket = UnixDatagram::unbound()?;
+            socket.set_write_timeout(write_timeout)?;
+            Ok(Box::new(Unix { socket, path: path.clone() }))
+        }
+        #[cfg(not(unix))]
+        Endpoint::Unix(_) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "unix domain sockets are not supported on this platform",
+        )),
+    }
+}
+
+struct Udp {
+    socket: UdpSocket,
+    agent: String,
+}
+
+impl Transport for Udp {
+    fn send(&mut self, datagram: &[u8]) -> io::Result<()> {
+        self.socket.send_to(datagram, self.agent.as_str()).map(|_| ())
+    }
+}
+
+// The socket is never connected: each datagram is addressed to the path, so
+// an agent that starts late or restarts is picked up without reconnecting.
+#[cfg(unix)]
+struct Unix {
+    socket: UnixDatagram,
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl Transport for Unix {
+    fn send(&mut self, datagram: &[u8]) -> io::Result<()> {
+        self.socket.send_to(datagram, &self.path).map(|_| ())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use log::{Level, Log, Record};
+
+    use config::DatadogConfig;
+    use datadog::DatadogSink;
+    use logger::Builder;
+
+    fn socket_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("funky-logger-{}-{}.sock", name, process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn sends_to_a_unix_socket() {
+        let path = socket_path("transport");
+        let agent = UnixDatagram::bind(&path).unwrap();
+        agent.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let mut transport = open(&Endpoint::Unix(path.clone()), None).unwrap();
+        transport.send(b"_e{1,1}:a|b").unwrap();
+
+        let mut buf = [0; 64];
+        let n = agent.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"_e{1,1}:a|b");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn logger_sends_events_over_a_unix_socket() {
+        let path = socket_path("logger");
+        let agent = UnixDatagram::bind(&path).unwrap();
+        agent.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let sink = DatadogSink::new(DatadogConfig::new().socket_path(&path).tag_key("disk")).unwrap();
+        let logger = Builder::new(Some(sink)).parse("warn").build();
+        logger.log(&Record::builder()
+            .args(format_args!("disk almost full"))
+            .level(Level::Warn)
+            .module_path(Some("app::disk"))
+            .file(Some("src/disk.rs"))
+            .line(Some(7))
+            .key_values(&[("disk", "sda1"), ("pct", "97")])
+            .build());
+        logger.flush();
+
+        let mut buf = [0; 512];
+        let n = agent.recv(&mut buf).unwrap();
+        let datagram = String::from_utf8_lossy(&buf[..n]);
+        assert!(datagram.contains("|disk almost full pct=97|d:"), "{}", datagram);
+        assert!(datagram.contains("|p:normal|t:warning|#"), "{}", datagram);
+        assert!(datagram.ends_with("level:warning,module:app::disk,disk:sda1"), "{}", datagram);
+        fs::remove_file(&path).unwrap();
+    }
+}
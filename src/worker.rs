@@ -0,0 +1,548 @@
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use stats;
+
+thread_local! {
+    static ON_WORKER: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether this is a worker thread. Records its sinks log, like the HTTP
+/// client's own debug output, would otherwise feed back into its queue.
+pub(crate) fn on_worker() -> bool {
+    ON_WORKER.with(Cell::get)
+}
+
+/// What to do with a record when the Datadog queue is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Drop the record being logged.
+    #[default]
+    DropNewest,
+    /// Drop the oldest queued record to make room.
+    DropOldest,
+    /// Block the logging thread until there is room.
+    Block,
+}
+
+/// Receives queued items on the worker thread.
+pub(crate) trait Handler<T>: Send {
+    fn handle(&mut self, item: T);
+
+    /// Called once the queue runs empty after handling items, and again
+    /// after the delay it last returned. Handlers that batch or retry do
+    /// whatever is due here and say when to check again.
+    fn idle(&mut self) -> Option<Duration> {
+        None
+    }
+
+    /// Sends everything held back, for `Worker::flush`. Returns whether it
+    /// was all delivered.
+    fn flush(&mut self) -> bool {
+        true
+    }
+}
+
+impl<T, F: FnMut(T) + Send> Handler<T> for F {
+    fn handle(&mut self, item: T) {
+        self(item)
+    }
+}
+
+struct State<T> {
+    items: VecDeque<T>,
+    in_flight: bool,
+    // items were handled since the handler last went idle
+    dirty: bool,
+    closed: bool,
+    // flushes asked for and done; `flush_ok` is the result of the last one
+    flush_requested: u64,
+    flushed: u64,
+    flush_ok: bool,
+    exited: bool,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    changed: Condvar,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn wait<'a>(&self, guard: MutexGuard<'a, State<T>>) -> MutexGuard<'a, State<T>> {
+        self.changed.wait(guard).unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Waits for a change, or returns `None` once `deadline` has passed.
+    /// Without a deadline it waits as long as it takes.
+    fn wait_until<'a>(&self, guard: MutexGuard<'a, State<T>>, deadline: Option<Instant>)
+        -> Option<MutexGuard<'a, State<T>>>
+    {
+        let deadline = match deadline {
+            Some(deadline) => deadline,
+            None => return Some(self.wait(guard)),
+        };
+        let now = Instant::now();
+        if now >= deadline {
+            return None;
+        }
+        Some(self.changed
+            .wait_timeout(guard, deadline - now)
+            .unwrap_or_else(|e| e.into_inner())
+            .0)
+    }
+}
+
+/// `timeout` from now, or `None` if that is too far off to represent.
+fn after(timeout: Duration) -> Option<Instant> {
+    Instant::now().checked_add(timeout)
+}
+
+/// A bounded queue drained by a dedicated thread.
+pub(crate) struct Worker<T> {
+    shared: Arc<Shared<T>>,
+    capacity: usize,
+    overflow: Overflow,
+    flush_timeout: Duration,
+    block_timeout: Option<Duration>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl<T: Send + 'static> Worker<T> {
+    pub fn spawn<H>(capacity: usize, overflow: Overflow, flush_timeout: Duration, mut handler: H)
+        -> io::Result<Worker<T>>
+        where H: Handler<T> + 'static
+    {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                items: VecDeque::with_capacity(capacity),
+                in_flight: false,
+                dirty: false,
+                closed: false,
+                flush_requested: 0,
+                flushed: 0,
+                flush_ok: true,
+                exited: false,
+            }),
+            changed: Condvar::new(),
+        });
+
+        let thread_shared = shared.clone();
+        let thread = thread::Builder::new()
+            .name("funky-logger".into())
+            .spawn(move || {
+                ON_WORKER.with(|on_worker| on_worker.set(true));
+                let shared = thread_shared;
+                let _exit = Exit(&shared);
+                let mut wake: Option<Instant> = None;
+                let mut state = shared.lock();
+                loop {
+                    if let Some(item) = state.items.pop_front() {
+                        state.in_flight = true;
+                        drop(state);
+                        shared.changed.notify_all();
+
+                        if contain(|| handler.handle(item)).is_none() {
+                            stats::record_dropped();
+                        }
+
+                        state = shared.lock();
+                        state.in_flight = false;
+                        state.dirty = true;
+                        shared.changed.notify_all();
+                    } else if state.flushed < state.flush_requested {
+                        let requested = state.flush_requested;
+                        state.in_flight = true;
+                        drop(state);
+
+                        let ok = contain(|| handler.flush()).unwrap_or(false);
+
+                        state = shared.lock();
+                        state.in_flight = false;
+                        state.flushed = requested;
+                        state.flush_ok = ok;
+                        shared.changed.notify_all();
+                    } else if state.closed {
+                        break;
+                    } else if state.dirty || wake.is_some_and(|wake| wake <= Instant::now()) {
+                        state.in_flight = true;
+                        drop(state);
+
+                        wake = contain(|| handler.idle())
+                            .and_then(|delay| delay)
+                            .and_then(after);
+
+                        state = shared.lock();
+                        state.in_flight = false;
+                        state.dirty = false;
+                        shared.changed.notify_all();
+                    } else if let Some(at) = wake {
+                        let timeout = at.saturating_duration_since(Instant::now());
+                        state = shared.changed
+                            .wait_timeout(state, timeout)
+                            .unwrap_or_else(|e| e.into_inner())
+                            .0;
+                    } else {
+                        state = shared.wait(state);
+                    }
+                }
+
+                // whatever is still held back goes out before the thread ends
+                drop(state);
+                let ok = contain(|| handler.flush()).unwrap_or(false);
+                let _ = contain(move || drop(handler));
+                shared.lock().flush_ok = ok;
+            })?;
+
+        Ok(Worker {
+            shared,
+            capacity: capacity.max(1),
+            overflow,
+            flush_timeout,
+            block_timeout: None,
+            thread: Mutex::new(Some(thread)),
+        })
+    }
+}
+
+/// Runs `f`, keeping a panic in a sink or error hook from taking the worker
+/// thread down with it.
+fn contain<R, F: FnOnce() -> R>(f: F) -> Option<R> {
+    panic::catch_unwind(AssertUnwindSafe(f)).ok()
+}
+
+/// Marks the queue closed when the worker thread ends, however it ends, so
+/// nothing waits on it forever.
+struct Exit<'a, T: 'a>(&'a Shared<T>);
+
+impl<'a, T> Drop for Exit<'a, T> {
+    fn drop(&mut self) {
+        let mut state = self.0.lock();
+        for _ in state.items.drain(..) {
+            stats::record_dropped();
+        }
+        state.closed = true;
+        state.in_flight = false;
+        state.flushed = state.flush_requested;
+        state.exited = true;
+        self.0.changed.notify_all();
+    }
+}
+
+impl<T> Worker<T> {
+    /// Limits how long `Overflow::Block` waits for room.
+    pub fn block_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.block_timeout = timeout;
+        self
+    }
+
+    /// Has the handler's `idle` called soon, as if an item had been handled.
+    pub fn wake(&self) {
+        self.shared.lock().dirty = true;
+        self.shared.changed.notify_all();
+    }
+
+    /// Queues an item, applying the overflow policy if the queue is full.
+    pub fn push(&self, item: T) {
+        // a timeout too far off to represent waits as long as it takes
+        let deadline = self.block_timeout.and_then(after);
+        let mut state = self.shared.lock();
+        if state.closed {
+            stats::record_dropped();
+            return;
+        }
+
+        while state.items.len() >= self.capacity {
+            match self.overflow {
+                Overflow::DropNewest => {
+                    stats::record_dropped();
+                    return;
+                }
+                Overflow::DropOldest => {
+                    state.items.pop_front();
+                    stats::record_dropped();
+                }
+                Overflow::Block => {
+                    state = match self.shared.wait_until(state, deadline) {
+                        Some(state) => state,
+                        None => {
+                            stats::record_dropped();
+                            return;
+                        }
+                    };
+                    if state.closed {
+                        stats::record_dropped();
+                        return;
+                    }
+                }
+            }
+        }
+
+        state.items.push_back(item);
+        drop(state);
+        self.shared.changed.notify_all();
+    }
+
+    /// Waits until everything queued so far has been handled and sent, or
+    /// until the flush timeout passes. Returns whether it was all delivered.
+    pub fn flush(&self) -> bool {
+        let deadline = after(self.flush_timeout);
+        let mut state = self.shared.lock();
+        if state.exited {
+            return state.flush_ok && state.items.is_empty();
+        }
+        state.flush_requested += 1;
+        let requested = state.flush_requested;
+        self.shared.changed.notify_all();
+
+        while state.flushed < requested {
+            state = match self.shared.wait_until(state, deadline) {
+                Some(state) => state,
+                None => return false,
+            };
+        }
+        state.flush_ok
+    }
+
+    /// Stops accepting items, drains the queue and joins the thread.
+    ///
+    /// If the queue can't be drained within the flush timeout the thread is
+    /// left to finish on its own.
+    pub fn shutdown(&self) {
+        self.close();
+        let deadline = after(self.flush_timeout);
+        let mut state = self.shared.lock();
+        while !state.exited {
+            state = match self.shared.wait_until(state, deadline) {
+                Some(state) => state,
+                None => return,
+            };
+        }
+        drop(state);
+
+        let thread = self.thread.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(thread) = thread {
+            let _ = thread.join();
+        }
+    }
+
+    fn close(&self) {
+        self.shared.lock().closed = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+impl<T> Drop for Worker<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+trait Flush: Send + Sync {
+    fn flush(&self) -> bool;
+    fn shutdown(&self);
+}
+
+impl<T: Send> Flush for Worker<T> {
+    fn flush(&self) -> bool {
+        Worker::flush(self)
+    }
+
+    fn shutdown(&self) {
+        Worker::shutdown(self)
+    }
+}
+
+static WORKERS: Mutex<Vec<Weak<dyn Flush>>> = Mutex::new(Vec::new());
+
+/// Adds `worker` to the ones targeted by `flush()` and `FlushGuard`.
+pub(crate) fn register<T: Send + 'static>(worker: &Arc<Worker<T>>) {
+    let worker: Arc<dyn Flush> = worker.clone();
+    let mut workers = WORKERS.lock().unwrap_or_else(|e| e.into_inner());
+    workers.retain(|worker| worker.strong_count() > 0);
+    workers.push(Arc::downgrade(&worker));
+}
+
+fn live() -> Vec<Arc<dyn Flush>> {
+    WORKERS.lock().unwrap_or_else(|e| e.into_inner()).iter().filter_map(Weak::upgrade).collect()
+}
+
+/// Blocks until every record queued for Datadog so far has been sent, or the
+/// flush timeout passes. Returns whether it was all delivered; lines a TCP
+/// sink still holds while disconnected count as undelivered.
+pub fn flush() -> bool {
+    // every sender gets flushed even after one fails
+    let mut ok = true;
+    for worker in live() {
+        ok &= worker.flush();
+    }
+    ok
+}
+
+/// Stops the Datadog senders, draining their queues first.
+///
+/// Records logged afterwards still reach the console but are dropped
+/// instead of being sent, as are lines a TCP sink couldn't deliver by then.
+pub fn shutdown() {
+    for worker in live() {
+        worker.shutdown();
+    }
+}
+
+/// Shuts down the Datadog senders when dropped.
+///
+/// Keep one alive in `main` so queued records are sent before the process
+/// exits:
+///
+/// ```no_run
+/// funky_logger::init();
+/// let _guard = funky_logger::flush_guard();
+/// ```
+#[must_use = "the queue is drained when the guard is dropped"]
+#[derive(Debug)]
+pub struct FlushGuard(());
+
+pub fn flush_guard() -> FlushGuard {
+    FlushGuard(())
+}
+
+impl Drop for FlushGuard {
+    fn drop(&mut self) {
+        shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    // The handler blocks on `gate` until the test releases it, so the first
+    // item sits in flight while the rest fill the queue.
+    fn gated(overflow: Overflow) -> (Worker<u32>, mpsc::Sender<()>, mpsc::Receiver<u32>) {
+        let (gate_tx, gate_rx) = mpsc::channel::<()>();
+        let (out_tx, out_rx) = mpsc::channel();
+        let worker = Worker::spawn(2, overflow, Duration::from_secs(5), move |n| {
+            let _ = gate_rx.recv();
+            out_tx.send(n).unwrap();
+        }).unwrap();
+        (worker, gate_tx, out_rx)
+    }
+
+    fn fill(worker: &Worker<u32>) {
+        worker.push(1);
+        while !worker.shared.lock().in_flight {
+            thread::yield_now();
+        }
+        for n in 2..6 {
+            worker.push(n);
+        }
+    }
+
+    fn release(gate: &mpsc::Sender<()>, worker: &Worker<u32>) {
+        for _ in 0..5 {
+            let _ = gate.send(());
+        }
+        assert!(worker.flush());
+    }
+
+    #[test]
+    fn drop_newest_keeps_the_head_of_the_queue() {
+        let (worker, gate, out) = gated(Overflow::DropNewest);
+        fill(&worker);
+        release(&gate, &worker);
+        assert_eq!(out.try_iter().collect::<Vec<_>>(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_tail_of_the_queue() {
+        let (worker, gate, out) = gated(Overflow::DropOldest);
+        fill(&worker);
+        release(&gate, &worker);
+        assert_eq!(out.try_iter().collect::<Vec<_>>(), vec![1, 4, 5]);
+    }
+
+    #[test]
+    fn shutdown_drains_and_rejects_new_items() {
+        let (out_tx, out_rx) = mpsc::channel();
+        let worker = Worker::spawn(16, Overflow::Block, Duration::from_secs(5), move |n| {
+            out_tx.send(n).unwrap();
+        }).unwrap();
+        for n in 0..10 {
+            worker.push(n);
+        }
+        worker.shutdown();
+        worker.push(10);
+        assert_eq!(out_rx.try_iter().collect::<Vec<_>>(), (0..10).collect::<Vec<_>>());
+        assert!(worker.thread.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn flush_reaches_every_registered_worker() {
+        let (tx, rx) = mpsc::channel();
+        let workers: Vec<_> = (0..2).map(|_| {
+            let tx = tx.clone();
+            let worker = Arc::new(Worker::spawn(4, Overflow::Block, Duration::from_secs(5), move |n| {
+                thread::sleep(Duration::from_millis(20));
+                tx.send(n).unwrap();
+            }).unwrap());
+            register(&worker);
+            worker
+        }).collect();
+
+        workers[0].push(1);
+        workers[1].push(2);
+        // other tests register senders too, so only delivery is checked
+        flush();
+
+        let mut sent: Vec<u32> = rx.try_iter().collect();
+        sent.sort();
+        assert_eq!(sent, vec![1, 2]);
+    }
+
+    #[test]
+    fn survives_a_panicking_handler() {
+        let (tx, rx) = mpsc::channel();
+        let worker = Worker::spawn(4, Overflow::Block, Duration::from_secs(5), move |n| {
+            assert!(n != 1, "handler failed");
+            tx.send(n).unwrap();
+        }).unwrap();
+
+        let dropped = stats::stats().dropped;
+        worker.push(1);
+        worker.push(2);
+        assert!(worker.flush());
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![2]);
+        assert!(stats::stats().dropped > dropped);
+    }
+
+    #[test]
+    fn flush_timeout_may_be_unbounded() {
+        let worker = Worker::spawn(4, Overflow::Block, Duration::MAX, |_: u32| {}).unwrap();
+        worker.push(1);
+        assert!(worker.flush());
+        worker.shutdown();
+    }
+
+    #[test]
+    fn block_timeout_may_be_unbounded() {
+        let (tx, rx) = mpsc::channel();
+        let worker = Worker::spawn(1, Overflow::Block, Duration::from_secs(5), move |n: u32| {
+            tx.send(n).unwrap();
+        }).unwrap().block_timeout(Some(Duration::MAX));
+
+        for n in 0..3 {
+            worker.push(n);
+        }
+        assert!(worker.flush());
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![0, 1, 2]);
+    }
+}
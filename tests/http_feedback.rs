@@ -0,0 +1,85 @@
+#![cfg(feature = "http")]
+
+extern crate funky_logger;
+#[macro_use]
+extern crate log;
+
+use std::env;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use funky_logger::{DatadogConfig, HttpLogsConfig};
+
+// Needs a process of its own: the HTTP client only logs through the global
+// logger.
+#[test]
+fn records_logged_while_sending_are_not_sent() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/api/v2/logs", listener.local_addr().unwrap());
+    let requests = Arc::new(AtomicUsize::new(0));
+
+    let counter = requests.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            // keep-alive: answer requests until the client hangs up
+            loop {
+                let mut len = 0;
+                let mut line = String::new();
+                loop {
+                    line.clear();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                        break;
+                    }
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                        len = value.trim().parse().unwrap();
+                    }
+                }
+                if line.is_empty() {
+                    break;
+                }
+                let mut body = vec![0; len];
+                reader.read_exact(&mut body).unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                stream.write_all(b"HTTP/1.1 202 Accepted\r\nContent-Length: 2\r\n\r\n{}").unwrap();
+            }
+        }
+    });
+
+    let config = DatadogConfig::new()
+        .events(false)
+        .http_logs(HttpLogsConfig::new().url(url).api_key("secret").gzip(false));
+    funky_logger::formatted_builder_with(config).unwrap().parse("debug").try_init().unwrap();
+
+    info!("only this is sent");
+    assert!(funky_logger::flush());
+    thread::sleep(Duration::from_millis(200));
+    assert!(funky_logger::flush());
+
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+}
+
+// The HTTP client's debug records include request headers, so they must not
+// reach the console either. Runs the test above in a child process to see
+// its stderr.
+#[test]
+fn api_key_stays_off_the_console() {
+    let output = Command::new(env::current_exe().unwrap())
+        .args(["records_logged_while_sending_are_not_sent", "--exact", "--nocapture"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("only this is sent"), "{}", stderr);
+    assert!(!stderr.contains("secret"), "{}", stderr);
+}
@@ -0,0 +1,41 @@
+#![cfg(feature = "tracing")]
+
+extern crate funky_logger;
+#[macro_use]
+extern crate log;
+extern crate tracing;
+extern crate tracing_subscriber;
+
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use tracing_subscriber::layer::SubscriberExt;
+
+use funky_logger::DatadogConfig;
+
+// Needs a process of its own: it installs the global logger.
+#[test]
+fn log_and_tracing_share_one_sender() {
+    let agent = UdpSocket::bind("127.0.0.1:0").unwrap();
+    agent.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let config = DatadogConfig::new()
+        .host("127.0.0.1")
+        .port(agent.local_addr().unwrap().port());
+
+    let layer = funky_logger::formatted_builder_with(config).unwrap().parse("info").init_layer();
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("from tracing");
+    });
+    info!("from log");
+    assert!(funky_logger::flush());
+
+    let mut buf = [0; 512];
+    let (n, first) = agent.recv_from(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).contains("|from tracing|"));
+    let (n, second) = agent.recv_from(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).contains("|from log|"));
+
+    // one sender, so one socket
+    assert_eq!(first, second);
+}